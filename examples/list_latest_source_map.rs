@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use anyhow::anyhow;
 use fapt::commands;
+use fapt::parse::Version;
 use fapt::rfc822::RfcMapExt;
 use fapt::system::System;
 
@@ -43,17 +44,13 @@ async fn main() -> Result<(), anyhow::Error> {
             assert!(package_version_files
                 .entry(pkg.to_string())
                 .or_insert_with(HashMap::new)
-                .insert(version.to_string(), urls)
+                .insert(version.parse::<Version>()?, urls)
                 .is_none());
         }
     }
 
     for (package, version_files) in package_version_files {
-        let best = version_files
-            .keys()
-            .into_iter()
-            .max_by(|left, right| deb_version::compare_versions(left, right))
-            .unwrap();
+        let best = version_files.keys().max().unwrap();
         for file in &version_files[best] {
             println!("{} {} {}", package, best, file);
         }
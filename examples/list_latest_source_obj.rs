@@ -35,17 +35,13 @@ async fn main() -> Result<(), anyhow::Error> {
             assert!(package_version_files
                 .entry(pkg.name.to_string())
                 .or_insert_with(HashMap::new)
-                .insert(pkg.version.to_string(), urls)
+                .insert(pkg.version, urls)
                 .is_none());
         }
     }
 
     for (package, version_files) in package_version_files {
-        let best = version_files
-            .keys()
-            .into_iter()
-            .max_by(|left, right| deb_version::compare_versions(left, right))
-            .unwrap();
+        let best = version_files.keys().max().unwrap();
         for file in &version_files[best] {
             println!("{} {} {}", package, best, file);
         }
@@ -1,3 +1,4 @@
+use std::ffi::OsStr;
 use std::fs;
 use std::io;
 use std::path::Path;
@@ -9,9 +10,15 @@ use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Error;
 use clap::{command, Arg, Command};
+use fapt::bootstrap;
 use fapt::commands;
+use fapt::download;
+use fapt::parse::Version;
+use fapt::policy::Policy;
+use fapt::policy::Preferences;
 use fapt::sources_list;
 use fapt::system::System;
+use fapt::upgradable;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -59,6 +66,19 @@ async fn main() -> Result<(), anyhow::Error> {
                 .number_of_values(1)
                 .help("an explicit arch (e.g. 'amd64'); the first provided will be the 'primary'"),
         )
+        .arg(
+            Arg::new("max-valid-time")
+                .long("max-valid-time")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(i64))
+                .help("reject releases older than this, regardless of their Valid-Until"),
+        )
+        .arg(
+            Arg::new("preferences")
+                .long("preferences")
+                .value_name("PREFIX")
+                .help("explicitly set the apt preferences (pinning) search path"),
+        )
         .arg(
             Arg::new("system-dpkg")
                 .long("system-dpkg")
@@ -72,20 +92,90 @@ async fn main() -> Result<(), anyhow::Error> {
         .subcommand(
             Command::new("source-ninja"), // .help("dump out all source packages as ninja"),
         )
+        .subcommand(
+            Command::new("check-installable")
+                .about("list binary packages which can't be installed, and why"),
+        )
+        .subcommand(
+            Command::new("rdepends")
+                .about("list the packages which refer to a package")
+                .arg(Arg::new("package").required(true))
+                .arg(
+                    Arg::new("build")
+                        .long("build")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("include source packages' build relationships"),
+                ),
+        )
+        .subcommand(
+            Command::new("policy")
+                .about("show the priorities of a package's versions, and which would be installed")
+                .arg(Arg::new("package").required(true))
+                .arg(
+                    Arg::new("target-release")
+                        .long("target-release")
+                        .short('t')
+                        .value_name("RELEASE")
+                        .help("prefer versions from this suite or codename"),
+                ),
+        )
+        .subcommand(
+            Command::new("download")
+                .about("download a binary package's .deb, checking its hashes")
+                .arg(
+                    Arg::new("package")
+                        .required(true)
+                        .value_name("PACKAGE[=VERSION]"),
+                )
+                .arg(dest_arg()),
+        )
+        .subcommand(
+            Command::new("source")
+                .about("download the files making up a source package, checking their hashes")
+                .arg(
+                    Arg::new("package")
+                        .required(true)
+                        .value_name("PACKAGE[=VERSION]"),
+                )
+                .arg(dest_arg()),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("list installed packages, and the newest versions available")
+                .arg(
+                    Arg::new("upgradable")
+                        .long("upgradable")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("only packages which are upgradable, held, obsolete or newer"),
+                ),
+        )
+        .subcommand(
+            Command::new("bootstrap")
+                .about("unpack the required packages into a directory, without running any scripts")
+                .arg(Arg::new("root").required(true).value_name("DIRECTORY"))
+                .arg(
+                    Arg::new("include")
+                        .long("include")
+                        .value_name("PACKAGE")
+                        .num_args(..)
+                        .number_of_values(1)
+                        .help("also install this package"),
+                ),
+        )
         .get_matches();
 
     let mut sources_entries = Vec::with_capacity(16);
-    if let Some(prefix) = matches.get_one::<&str>("sources-list") {
-        for prefix in expand_dot_d(prefix)? {
+    if let Some(prefix) = matches.get_one::<String>("sources-list") {
+        for prefix in expand_dot_d(prefix, &["sources"])? {
             sources_entries.extend(
-                sources_list::read(io::BufReader::new(fs::File::open(&prefix)?))
+                read_sources_file(&prefix)
                     .with_context(|| anyhow!("loading sources.list: {:?}", prefix))?,
             );
         }
     }
 
-    if let Some(lines) = matches.get_many::<&str>("sources-line") {
-        for line in lines.copied() {
+    if let Some(lines) = matches.get_many::<String>("sources-line") {
+        for line in lines {
             let entries = sources_list::read(io::Cursor::new(line))
                 .with_context(|| anyhow!("parsing command line: {:?}", line))?;
 
@@ -99,9 +189,9 @@ async fn main() -> Result<(), anyhow::Error> {
         }
     }
 
-    let arches = match matches.get_many("arch") {
-        Some(arches) => arches.copied().collect(),
-        None => vec!["amd64"],
+    let arches: Vec<String> = match matches.get_many::<String>("arch") {
+        Some(arches) => arches.cloned().collect(),
+        None => vec!["amd64".to_string()],
     };
 
     if sources_entries.is_empty() {
@@ -115,7 +205,7 @@ async fn main() -> Result<(), anyhow::Error> {
     system.add_sources_entries(sources_entries.clone().into_iter());
     if let Some(keyring_paths) = matches.get_raw("keyring") {
         for keyring_path in keyring_paths {
-            for path in expand_dot_d(keyring_path)? {
+            for path in expand_dot_d(keyring_path, &[])? {
                 system.add_keys_from(
                     fs::File::open(&path)
                         .with_context(|| anyhow!("opening key file: {:?}", path))?,
@@ -128,7 +218,11 @@ async fn main() -> Result<(), anyhow::Error> {
 
     system.set_arches(&arches);
 
-    system.set_dpkg_database(matches.get_one::<&Path>("system-dpkg").unwrap());
+    if let Some(seconds) = matches.get_one::<i64>("max-valid-time") {
+        system.set_max_valid_time(Some(chrono::Duration::seconds(*seconds)));
+    }
+
+    system.set_dpkg_database(matches.get_one::<String>("system-dpkg").unwrap());
 
     match matches.subcommand() {
         Some(("source-ninja", _)) => {
@@ -137,13 +231,131 @@ async fn main() -> Result<(), anyhow::Error> {
         Some(("update", _)) => {
             system.update().await?;
         }
+        Some(("check-installable", _)) => {
+            for (package, problem) in commands::check_installable(&system, &arches[0])? {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    package.name,
+                    package.version,
+                    problem.kind(),
+                    problem
+                );
+            }
+        }
+        Some(("rdepends", args)) => {
+            commands::rdepends(
+                &system,
+                args.get_one::<String>("package").unwrap(),
+                args.get_flag("build"),
+            )?;
+        }
+        Some(("policy", args)) => {
+            let mut preferences = Preferences::default();
+            if let Some(prefix) = matches.get_one::<String>("preferences") {
+                for path in expand_dot_d(prefix, &["pref"])? {
+                    preferences.extend(
+                        Preferences::parse(io::BufReader::new(fs::File::open(&path)?))
+                            .with_context(|| anyhow!("loading preferences: {:?}", path))?,
+                    );
+                }
+            }
+
+            let mut policy = Policy::new(preferences);
+            policy.set_target_release(args.get_one::<String>("target-release").cloned());
+            commands::policy(&system, &policy, args.get_one::<String>("package").unwrap())?;
+        }
+        Some(("download", args)) => {
+            let (name, version) = package_version(args.get_one::<String>("package").unwrap())?;
+            let file = download::binary_file(&system, name, version.as_ref(), &arches[0])?;
+            for path in
+                download::download_to(&system, &[file], args.get_one::<String>("dest").unwrap())
+                    .await?
+            {
+                println!("{}", path.display());
+            }
+        }
+        Some(("source", args)) => {
+            let (name, version) = package_version(args.get_one::<String>("package").unwrap())?;
+            let files = download::source_files(&system, name, version.as_ref())?;
+            for path in
+                download::download_to(&system, &files, args.get_one::<String>("dest").unwrap())
+                    .await?
+            {
+                println!("{}", path.display());
+            }
+        }
+        Some(("bootstrap", args)) => {
+            let include: Vec<&String> = args
+                .get_many::<String>("include")
+                .map(|v| v.collect())
+                .unwrap_or_default();
+            for package in bootstrap::bootstrap(
+                &system,
+                &arches[0],
+                &include,
+                args.get_one::<String>("root").unwrap(),
+            )
+            .await?
+            {
+                println!("{}\t{}", package.name, package.version);
+            }
+        }
+        Some(("list", args)) => {
+            for installed in upgradable::report(&system)? {
+                let kind = installed.kind();
+                if args.get_flag("upgradable") && upgradable::Kind::UpToDate == kind {
+                    continue;
+                }
+                let (newest, archive) = match &installed.newest {
+                    Some((version, origin)) => (
+                        version.to_string(),
+                        origin.archive.clone().unwrap_or_default(),
+                    ),
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    installed.identity(),
+                    installed.version,
+                    newest,
+                    archive,
+                    kind
+                );
+            }
+        }
         _ => unreachable!(),
     }
 
     Ok(())
 }
 
-fn expand_dot_d<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, Error> {
+fn dest_arg() -> Arg {
+    Arg::new("dest")
+        .long("dest")
+        .short('d')
+        .value_name("DIRECTORY")
+        .default_value(".")
+        .help("where to write the files")
+}
+
+/// `foo` or `foo=1.2-3`
+fn package_version(arg: &str) -> Result<(&str, Option<Version>), Error> {
+    Ok(match arg.split_once('=') {
+        Some((name, version)) => (name, Some(version.parse()?)),
+        None => (arg, None),
+    })
+}
+
+fn read_sources_file(path: &Path) -> Result<Vec<sources_list::Entry>, Error> {
+    let file = io::BufReader::new(fs::File::open(path)?);
+    if path.extension() == Some(OsStr::new("sources")) {
+        sources_list::read_deb822(file)
+    } else {
+        sources_list::read(file)
+    }
+}
+
+fn expand_dot_d<P: AsRef<Path>>(path: P, also: &[&str]) -> Result<Vec<PathBuf>, Error> {
     let mut ret = Vec::new();
 
     let path = path.as_ref();
@@ -166,7 +378,11 @@ fn expand_dot_d<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, Error> {
     if dot_d.is_dir() {
         for file in fs::read_dir(dot_d)? {
             let file = file?.path();
-            if file.is_file() && file.extension() == extension {
+            let wanted = file.extension() == extension
+                || also
+                    .iter()
+                    .any(|also| file.extension() == Some(OsStr::new(also)));
+            if file.is_file() && wanted {
                 ret.push(file);
             }
         }
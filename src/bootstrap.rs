@@ -0,0 +1,357 @@
+//! Build a minimal root filesystem from some _Listings_, like a very small `debootstrap`,
+//! without needing `root`.
+//!
+//! The `Essential: yes` and `Priority: required` packages (and their dependencies) are
+//! resolved, downloaded, and unpacked into a directory, and a `dpkg` database is written
+//! to describe them. Maintainer scripts are recorded in `var/lib/dpkg/info`, but never run,
+//! so the packages are left `unpacked`, not `installed`; and file ownership is not preserved.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use reqwest::Url;
+
+use crate::deb::Control;
+use crate::deb::DebReader;
+use crate::deb::EntryKind;
+use crate::download;
+use crate::dpkg::Database;
+use crate::parse::Package;
+use crate::parse::Priority;
+use crate::resolve::Resolver;
+use crate::system::System;
+
+/// How many symlinks we'll follow while finding where a path is, like `MAXSYMLINKS`.
+const MAX_LINKS: usize = 40;
+
+/// The result of unpacking one `.deb`.
+#[derive(Clone, Debug)]
+pub struct Unpacked {
+    pub control: Control,
+    /// Everything that was created, relative to the root, in archive order.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Fill `root` with the required packages for `arch`, and any `extra` packages requested.
+///
+/// The `.deb`s are kept in `var/cache/apt/archives` under `root`.
+pub async fn bootstrap<P: AsRef<Path>, S: AsRef<str>>(
+    system: &System,
+    arch: &str,
+    extra: &[S],
+    root: P,
+) -> Result<Vec<Package>, Error> {
+    let root = root.as_ref();
+
+    let mut available: Vec<(Package, Url)> = Vec::new();
+    for list in system.listings()? {
+        if list.listing.arch.is_none() {
+            continue;
+        }
+        for block in system.open_listing(&list)? {
+            let package = match block?.as_pkg() {
+                Ok(package) => package,
+                Err(e) => {
+                    writeln!(
+                        io::stderr(),
+                        "Skipping an invalid package in {} {}: {:?}",
+                        list.release.req.mirror,
+                        list.listing.component,
+                        e
+                    )?;
+                    continue;
+                }
+            };
+            available.push((package, list.release.req.mirror.clone()));
+        }
+    }
+
+    let mut wanted: Vec<String> = extra.iter().map(|s| s.as_ref().to_string()).collect();
+    for (package, _) in &mut available {
+        if is_required(package)? {
+            wanted.push(package.name.to_string());
+        }
+    }
+    wanted.sort();
+    wanted.dedup();
+
+    let packages: Vec<Package> = available.iter().map(|(p, _)| p.clone()).collect();
+    let resolver = Resolver::new(&packages, arch)?;
+    let chosen = resolver
+        .install(&wanted)
+        .map_err(|problem| anyhow!("resolving required packages: {}", problem))?;
+
+    let mut files = Vec::with_capacity(chosen.len());
+    for package in &chosen {
+        let (_, mirror) = available
+            .iter()
+            .find(|(p, _)| p.name == package.name && p.version == package.version)
+            .expect("resolver only returns packages it was given");
+        files.push(download::pool_file(mirror, package)?);
+    }
+
+    let archives = root.join("var/cache/apt/archives");
+    fs::create_dir_all(&archives)?;
+    let debs = download::download_to(system, &files, &archives).await?;
+
+    let dpkg = root.join("var/lib/dpkg");
+    let mut db = Database::open(&dpkg)?;
+    let mut ret = Vec::with_capacity(debs.len());
+
+    for deb in debs {
+        let unpacked = unpack(
+            fs::File::open(&deb).with_context(|| anyhow!("opening {:?}", deb))?,
+            root,
+        )
+        .with_context(|| anyhow!("unpacking {:?}", deb))?;
+
+        let control = String::from_utf8_lossy(&unpacked.control.files["control"]);
+        let name = db.add(&control, "install ok unpacked".parse()?)?;
+        db.set_files(&name, &unpacked.paths);
+        for (file, content) in &unpacked.control.files {
+            if "control" != file {
+                db.set_info(&name, file, content);
+            }
+        }
+
+        ret.push(unpacked.control.package);
+    }
+
+    db.commit()?;
+    fs::write(dpkg.join("available"), b"")?;
+
+    Ok(ret)
+}
+
+fn is_required(package: &mut Package) -> Result<bool, Error> {
+    Ok(match package.as_bin() {
+        Some(bin) if bin.essential => true,
+        Some(_) => Priority::Required == package.priority()?,
+        None => false,
+    })
+}
+
+/// Extract the `data.tar` of a `.deb` into `root`, returning the `control`.
+///
+/// Existing files are replaced. Ownership is ignored, but permissions and modification
+/// times are kept (where the platform supports them). Symlinks already in `root` are
+/// followed as if it was `/`, so nothing can be written outside of it.
+pub fn unpack<R: io::Read, P: AsRef<Path>>(deb: R, root: P) -> Result<Unpacked, Error> {
+    let root = root.as_ref();
+    let mut deb = DebReader::new(deb)?;
+    let control = deb.control()?;
+    let mut paths = Vec::new();
+
+    deb.data(|entry, content| {
+        ensure!(
+            entry
+                .path
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))),
+            "refusing to unpack outside of the root: {:?}",
+            entry.path
+        );
+
+        if entry.path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        // existing directories may be symlinks, e.g. `lib -> usr/lib`, which we keep
+        let follow_last = matches!(entry.kind, EntryKind::Directory);
+        let dest = resolve_in(root, &entry.path, follow_last)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if !matches!(entry.kind, EntryKind::Directory) && dest.symlink_metadata().is_ok() {
+            fs::remove_file(&dest)?;
+        }
+
+        match &entry.kind {
+            EntryKind::Directory => fs::create_dir_all(&dest)?,
+            EntryKind::File => {
+                io::copy(content, &mut fs::File::create(&dest)?)?;
+            }
+            EntryKind::Symlink(target) => symlink(target, &dest)?,
+            EntryKind::Hardlink(target) => fs::hard_link(resolve_in(root, target, false)?, &dest)?,
+            EntryKind::Other => {
+                // device nodes etc. need root; there are none in a minimal system
+                return Ok(());
+            }
+        }
+
+        if !matches!(entry.kind, EntryKind::Symlink(_)) {
+            set_mode(&dest, entry.mode)?;
+            let mtime = filetime::FileTime::from_unix_time(entry.mtime as i64, 0);
+            filetime::set_file_times(&dest, mtime, mtime)?;
+        }
+
+        paths.push(entry.path.clone());
+        Ok(())
+    })?;
+
+    Ok(Unpacked { control, paths })
+}
+
+/// Where `path`, relative to `root`, really is, following any symlinks on the way as if
+/// `root` was `/`: absolute targets are re-rooted, and `..` can't leave `root`.
+///
+/// The last component is only followed if `follow_last` is set.
+fn resolve_in(root: &Path, path: &Path, follow_last: bool) -> Result<PathBuf, Error> {
+    let mut resolved = PathBuf::new();
+    let mut pending: Vec<OsString> = names(path).into_iter().rev().collect();
+    let mut links = 0;
+
+    while let Some(name) = pending.pop() {
+        if ".." == name {
+            ensure!(resolved.pop(), "{:?} leads outside of the root", path);
+            continue;
+        }
+
+        let next = resolved.join(&name);
+        let follow = follow_last || !pending.is_empty();
+        match root.join(&next).symlink_metadata() {
+            Ok(meta) if follow && meta.file_type().is_symlink() => {
+                links += 1;
+                ensure!(
+                    links <= MAX_LINKS,
+                    "too many levels of symlinks: {:?}",
+                    path
+                );
+                let target = fs::read_link(root.join(&next))?;
+                if target.has_root() {
+                    resolved = PathBuf::new();
+                }
+                pending.extend(names(&target).into_iter().rev());
+            }
+            _ => resolved = next,
+        }
+    }
+
+    Ok(root.join(resolved))
+}
+
+/// The names in a path, including any `..`, but without any `.` or root.
+fn names(path: &Path) -> Vec<OsString> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, dest: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, dest)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, dest: &Path) -> Result<(), Error> {
+    anyhow::bail!("can't create symlinks on this platform: {:?}", dest)
+}
+
+#[cfg(unix)]
+fn set_mode(dest: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(dest, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_dest: &Path, _mode: u32) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use super::unpack;
+    use crate::deb::tests::deb_with;
+    use crate::deb::tests::file;
+    use crate::deb::tests::link;
+
+    #[test]
+    fn unpack_deb() {
+        let root = tempfile::tempdir().unwrap();
+        let unpacked = unpack(io::Cursor::new(crate::deb::tests::deb()), root.path()).unwrap();
+
+        assert_eq!("hello", unpacked.control.package.name);
+        assert_eq!(
+            vec![
+                PathBuf::from("usr/bin"),
+                PathBuf::from("usr/bin/hello"),
+                PathBuf::from("usr/bin/hi"),
+                PathBuf::from("usr/bin/hey"),
+            ],
+            unpacked.paths
+        );
+        assert_eq!(
+            b"\x7fELF".to_vec(),
+            fs::read(root.path().join("usr/bin/hey")).unwrap()
+        );
+
+        #[cfg(unix)]
+        assert_eq!(
+            PathBuf::from("hello"),
+            fs::read_link(root.path().join("usr/bin/hi")).unwrap()
+        );
+
+        // again, over the top
+        unpack(io::Cursor::new(crate::deb::tests::deb()), root.path()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unpack_through_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let absolute = outside.path().to_str().unwrap().to_string();
+
+        // an absolute symlink to a directory is followed inside the root, as in a chroot
+        let deb = deb_with(|b| {
+            link(b, tar::EntryType::Symlink, "./escape", &absolute);
+            file(b, "./escape/pwned", 0o644, b"pwned");
+        });
+        unpack(io::Cursor::new(deb), root.path()).unwrap();
+        assert!(!outside.path().join("pwned").exists());
+        assert_eq!(
+            b"pwned".to_vec(),
+            fs::read(
+                root.path()
+                    .join(outside.path().strip_prefix("/").unwrap())
+                    .join("pwned")
+            )
+            .unwrap()
+        );
+
+        // a relative one can't climb out
+        let deb = deb_with(|b| {
+            link(b, tar::EntryType::Symlink, "./up", "../..");
+            file(b, "./up/pwned", 0o644, b"pwned");
+        });
+        assert!(unpack(io::Cursor::new(deb), root.path()).is_err());
+
+        // hard links are resolved in the same way, so can't reach outside files
+        fs::write(outside.path().join("secret"), b"secret").unwrap();
+        let deb = deb_with(|b| {
+            link(b, tar::EntryType::Link, "./stolen", "./escape/secret");
+        });
+        assert!(unpack(io::Cursor::new(deb), root.path()).is_err());
+        assert!(!root.path().join("stolen").exists());
+    }
+}
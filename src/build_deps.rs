@@ -0,0 +1,333 @@
+//! Check whether a source _Package_ could be built, like `dose-builddebcheck`,
+//! or rebuilt exactly as described by a `.buildinfo`.
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use crate::parse::reduce_dependencies;
+use crate::parse::BuildInfo;
+use crate::parse::ConstraintOperator;
+use crate::parse::Dependency;
+use crate::parse::Package;
+use crate::parse::SingleDependency;
+use crate::parse::Source;
+use crate::parse::Version;
+use crate::resolve::Problem;
+use crate::resolve::Resolver;
+use crate::rfc822::RfcMapExt;
+use crate::system::System;
+
+/// Which binaries are being built, like `dpkg-buildpackage --build=`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildType {
+    /// Both architecture dependent and independent binaries.
+    Full,
+    /// Only the architecture dependent binaries, e.g. on a buildd.
+    Any,
+    /// Only the architecture independent (`Architecture: all`) binaries.
+    All,
+}
+
+/// Work out what needs to be installed to build `source` for the resolver's (host)
+/// architecture, with some build profiles (e.g. `nocheck`, `stage1`) enabled.
+///
+/// Architecture restrictions (`[armhf]`) are evaluated for the host architecture.
+/// To cross-build, use a `Resolver::cross`, so `:native` dependencies are satisfied
+/// on its build architecture.
+///
+/// If the build dependencies can't be satisfied, every clause which can't be satisfied
+/// on its own is reported. If they all can, but not together, the reason is reported.
+pub fn check<'p, S: AsRef<str>>(
+    resolver: &Resolver<'p>,
+    source: &Source,
+    build: BuildType,
+    profiles: &[S],
+) -> Result<Vec<&'p Package>, Vec<Problem>> {
+    let (depends, conflicts) = relations(source, build);
+    let reduce = |fields: Vec<&[Dependency]>| -> Vec<Dependency> {
+        fields
+            .into_iter()
+            .flat_map(|deps| reduce_dependencies(deps, resolver.arch(), profiles))
+            .collect()
+    };
+    let depends = reduce(depends);
+    let conflicts = reduce(conflicts);
+
+    let problems: Vec<Problem> = depends
+        .iter()
+        .filter_map(|clause| {
+            resolver
+                .resolve_avoiding(std::slice::from_ref(clause), &conflicts)
+                .err()
+        })
+        .collect();
+
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    resolver
+        .resolve_avoiding(&depends, &conflicts)
+        .map_err(|problem| vec![problem])
+}
+
+type Fields<'s> = Vec<&'s [Dependency]>;
+
+fn relations(source: &Source, build: BuildType) -> (Fields<'_>, Fields<'_>) {
+    let mut depends: Fields = vec![&source.build_dep];
+    let mut conflicts: Fields = vec![&source.build_conflict];
+
+    if BuildType::All != build {
+        depends.push(&source.build_dep_arch);
+        conflicts.push(&source.build_conflict_arch);
+    }
+
+    if BuildType::Any != build {
+        depends.push(&source.build_dep_indep);
+        conflicts.push(&source.build_conflict_indep);
+    }
+
+    (depends, conflicts)
+}
+
+/// The `Installed-Build-Depends` of a `.buildinfo` which are pinned to a version (`=`),
+/// but that version isn't in any of the binary _Listings_, so the build can't be reproduced.
+pub fn unavailable_build_depends(
+    system: &System,
+    info: &BuildInfo,
+) -> Result<Vec<SingleDependency>, Error> {
+    let mut available: HashMap<String, Vec<(String, Version)>> = HashMap::new();
+    for list in system.listings()? {
+        if list.listing.arch.is_none() {
+            continue;
+        }
+        for block in system.open_listing(&list)? {
+            let block = block?;
+            let map = block.as_map()?;
+            available
+                .entry(map.get_value("Package").one_line_req()?.to_string())
+                .or_default()
+                .push((
+                    map.get_value("Architecture").one_line_req()?.to_string(),
+                    map.get_value("Version").one_line_req()?.parse()?,
+                ));
+        }
+    }
+
+    Ok(unavailable(&info.installed_build_depends, &available))
+}
+
+fn unavailable(
+    installed: &[Dependency],
+    available: &HashMap<String, Vec<(String, Version)>>,
+) -> Vec<SingleDependency> {
+    installed
+        .iter()
+        .flat_map(|dep| &dep.alternate)
+        .filter(|dep| {
+            let pinned = match dep
+                .version_constraints
+                .iter()
+                .find(|c| ConstraintOperator::Eq == c.operator)
+            {
+                Some(constraint) => &constraint.version,
+                None => return false,
+            };
+            let arch = dep.arch.as_ref().map(|arch| arch.to_string());
+            !available.get(&dep.package).is_some_and(|versions| {
+                versions.iter().any(|(a, v)| {
+                    v == pinned && arch.as_ref().is_none_or(|arch| arch == a || "all" == a)
+                })
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::check;
+    use super::unavailable;
+    use super::BuildType;
+    use crate::parse::BuildInfo;
+    use crate::parse::Package;
+    use crate::resolve::Resolver;
+
+    fn source(build_depends: &str) -> Package {
+        Package::parse_test(&format!(
+            "Package: foo
+Binary: foo
+Version: 1
+Architecture: any
+Format: 3.0 (quilt)
+Directory: pool/main/f/foo
+Files:
+ d41d8cd98f00b204e9800998ecf8427e 0 foo_1.dsc
+{}",
+            build_depends
+        ))
+    }
+
+    #[test]
+    fn build_deps() {
+        let binaries = vec![
+            Package::parse_test("Package: gcc\nVersion: 1\nArchitecture: amd64"),
+            Package::parse_test("Package: libfoo-dev\nVersion: 1\nArchitecture: amd64"),
+            Package::parse_test("Package: bad\nVersion: 1\nArchitecture: amd64"),
+        ];
+        let resolver = Resolver::new(&binaries, "amd64").unwrap();
+        let none: &[&str] = &[];
+
+        let src = source(
+            "Build-Depends: gcc, libfoo-dev [amd64], missing [i386], check <!nocheck>
+Build-Depends-Indep: docs
+Build-Conflicts: bad",
+        );
+        let src = src.as_src().unwrap();
+
+        assert_eq!(
+            2,
+            check(&resolver, src, BuildType::Any, &["nocheck"])
+                .unwrap()
+                .len()
+        );
+
+        let problems = check(&resolver, src, BuildType::Full, none).unwrap_err();
+        let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            vec![
+                "check <!nocheck> is wanted, which is not available".to_string(),
+                "docs is wanted, which is not available".to_string(),
+            ],
+            problems
+        );
+
+        let src = source("Build-Depends: bad\nBuild-Conflicts: bad");
+        assert_eq!(
+            "bad 1 is needed, but conflicts with bad",
+            check(&resolver, src.as_src().unwrap(), BuildType::Any, none).unwrap_err()[0]
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn cross() {
+        let binaries = vec![
+            Package::parse_test("Package: gcc\nVersion: 1\nArchitecture: amd64"),
+            Package::parse_test("Package: libfoo-dev\nVersion: 1\nArchitecture: amd64"),
+            Package::parse_test("Package: libfoo-dev\nVersion: 1\nArchitecture: armhf"),
+            Package::parse_test(
+                "Package: make\nVersion: 1\nArchitecture: amd64\nMulti-Arch: foreign",
+            ),
+            Package::parse_test("Package: arm-only\nVersion: 1\nArchitecture: armhf"),
+        ];
+        let resolver = Resolver::cross(&binaries, "amd64", "armhf").unwrap();
+        let none: &[&str] = &[];
+
+        let src = source(
+            "Build-Depends: gcc:native, libfoo-dev, make, arm-only [armhf], amd-only [amd64]",
+        );
+        let mut found: Vec<String> = check(&resolver, src.as_src().unwrap(), BuildType::Any, none)
+            .unwrap()
+            .iter()
+            .map(|p| format!("{}:{}", p.name, p.arches.iter().next().unwrap()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec![
+                "arm-only:armhf",
+                "gcc:amd64",
+                "libfoo-dev:armhf",
+                "make:amd64"
+            ],
+            found
+        );
+
+        // the host's compiler isn't available
+        let src = source("Build-Depends: gcc");
+        assert!(check(&resolver, src.as_src().unwrap(), BuildType::Any, none).is_err());
+    }
+
+    #[test]
+    fn cross_multi_arch_same() {
+        let binaries = vec![
+            Package::parse_test("Package: gcc\nVersion: 1\nArchitecture: amd64\nDepends: libc6"),
+            Package::parse_test("Package: libc6\nVersion: 1\nArchitecture: amd64\nMulti-Arch: same"),
+            Package::parse_test("Package: libc6\nVersion: 1\nArchitecture: armhf\nMulti-Arch: same"),
+            Package::parse_test(
+                "Package: libc6-dev\nVersion: 1\nArchitecture: armhf\nMulti-Arch: same\nDepends: libc6",
+            ),
+            Package::parse_test("Package: libbar\nVersion: 1\nArchitecture: amd64"),
+            Package::parse_test("Package: libbar\nVersion: 1\nArchitecture: armhf"),
+            Package::parse_test("Package: tool\nVersion: 1\nArchitecture: amd64\nDepends: libbar"),
+        ];
+        let resolver = Resolver::cross(&binaries, "amd64", "armhf").unwrap();
+        let none: &[&str] = &[];
+
+        let src = source("Build-Depends: gcc:native, libc6-dev");
+        let mut found: Vec<String> = check(&resolver, src.as_src().unwrap(), BuildType::Any, none)
+            .unwrap()
+            .iter()
+            .map(|p| format!("{}:{}", p.name, p.arches.iter().next().unwrap()))
+            .collect();
+        found.sort();
+        assert_eq!(
+            vec!["gcc:amd64", "libc6-dev:armhf", "libc6:amd64", "libc6:armhf"],
+            found
+        );
+
+        // without `Multi-Arch: same`, only one architecture can be installed
+        let src = source("Build-Depends: tool:native, libbar");
+        assert_eq!(
+            "conflict",
+            check(&resolver, src.as_src().unwrap(), BuildType::Any, none).unwrap_err()[0].kind()
+        );
+    }
+
+    #[test]
+    fn reproducible() {
+        let info = BuildInfo::read(
+            &b"Format: 1.0
+Source: foo
+Architecture: amd64
+Version: 1
+Build-Architecture: amd64
+Installed-Build-Depends:
+ autoconf (= 2.71-3),
+ libc6:amd64 (= 2.36-9),
+ libc6-dev:i386 (= 2.36-9),
+ make (= 4.3-4.1),
+ unpinned (>= 1)
+"[..],
+            None,
+        )
+        .unwrap();
+        let v = |v: &str| v.parse().unwrap();
+        let mut available = HashMap::new();
+        available.insert(
+            "autoconf".to_string(),
+            vec![("all".to_string(), v("2.71-3"))],
+        );
+        available.insert(
+            "libc6".to_string(),
+            vec![("amd64".to_string(), v("2.36-9"))],
+        );
+        available.insert(
+            "libc6-dev".to_string(),
+            vec![("amd64".to_string(), v("2.36-9"))],
+        );
+        available.insert("make".to_string(), vec![("amd64".to_string(), v("4.3-4"))]);
+
+        let missing: Vec<String> = unavailable(&info.installed_build_depends, &available)
+            .iter()
+            .map(|dep| dep.to_string())
+            .collect();
+        assert_eq!(
+            vec!["libc6-dev:i386 (= 2.36-9)", "make (= 4.3-4.1)"],
+            missing
+        );
+    }
+}
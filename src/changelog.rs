@@ -0,0 +1,177 @@
+//! Find the changelog of a package, like `apt changelog`, from the `Changelogs` url
+//! template in its _Release_, e.g. `https://metadata.ftp-master.debian.org/changelogs/@CHANGEPATH@_changelog`.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use reqwest::Url;
+
+use crate::parse::parse_source;
+use crate::parse::Changelog;
+use crate::parse::Package;
+use crate::parse::PackageType;
+use crate::parse::Version;
+use crate::system::DownloadedList;
+use crate::system::System;
+
+/// The url of the changelog of a `package` from this _Listing_,
+/// or `None` if its _Release_ doesn't publish changelogs.
+pub fn url(list: &DownloadedList, package: &Package) -> Result<Option<Url>, Error> {
+    let template = match &list.release.file.changelogs {
+        Some(template) if "no" != template => template,
+        _ => return Ok(None),
+    };
+
+    let (source, version) = source_of(package)?;
+    Ok(Some(expand(
+        template,
+        &list.listing.component,
+        &source,
+        &version,
+    )?))
+}
+
+/// Where a changelog is cached, by its url, e.g.
+/// `changelogs/metadata.ftp-master.debian.org/changelogs/main/h/hello/hello_2.10-3_changelog`,
+/// next to the _Listings_.
+pub fn cache_path(system: &System, url: &Url) -> PathBuf {
+    let mut path = system.lists_dir.join("changelogs");
+    path.push(url.host_str().unwrap_or("localhost"));
+    for segment in url.path_segments().into_iter().flatten() {
+        // a parsed url never contains `.` or `..`
+        if !segment.is_empty() {
+            path.push(segment);
+        }
+    }
+    path
+}
+
+/// Read the cached copy of the changelog at this url, if it's been downloaded.
+pub fn read_cached(system: &System, url: &Url) -> Result<Option<Changelog>, Error> {
+    let path = cache_path(system, url);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(ref e) if io::ErrorKind::NotFound == e.kind() => return Ok(None),
+        Err(e) => return Err(e).with_context(|| anyhow!("opening {:?}", path)),
+    };
+
+    Ok(Some(
+        Changelog::parse(io::BufReader::new(file))
+            .with_context(|| anyhow!("parsing {:?}", path))?,
+    ))
+}
+
+/// The source name and version, for either type of package.
+fn source_of(package: &Package) -> Result<(String, Version), Error> {
+    let source = match &package.style {
+        PackageType::Source(_) => None,
+        PackageType::Binary(bin) => bin.source.as_deref(),
+    };
+
+    Ok(match source {
+        None => (package.name.to_string(), package.version.clone()),
+        Some(source) => {
+            let (name, version) = parse_source(source)?;
+            (name, version.unwrap_or_else(|| package.version.clone()))
+        }
+    })
+}
+
+/// Fill in `@CHANGEPATH@`, e.g. `main/h/hello/hello_2.10-3`, or `main/libf/libfoo/libfoo_1.0-1`.
+fn expand(template: &str, component: &str, source: &str, version: &Version) -> Result<Url, Error> {
+    ensure!(
+        source.is_ascii() && !source.is_empty(),
+        "invalid source name: {:?}",
+        source
+    );
+    let prefix = if source.starts_with("lib") && source.len() > 3 {
+        &source[..4]
+    } else {
+        &source[..1]
+    };
+
+    // the epoch isn't in the file names
+    let version = match version.revision() {
+        Some(revision) => format!("{}-{}", version.upstream(), revision),
+        None => version.upstream().to_string(),
+    };
+
+    let path = format!("{}/{}/{}/{}_{}", component, prefix, source, source, version);
+    let url = template.replace("@CHANGEPATH@", &path);
+    Url::parse(&url).with_context(|| anyhow!("invalid changelog url: {:?}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::cache_path;
+    use super::expand;
+    use super::read_cached;
+    use super::source_of;
+    use crate::parse::Package;
+    use crate::system::System;
+
+    const TEMPLATE: &str =
+        "https://metadata.ftp-master.debian.org/changelogs/@CHANGEPATH@_changelog";
+
+    #[test]
+    fn urls() {
+        let (source, version) = source_of(&Package::parse_test(
+            "Package: libfoo1\nSource: libfoo (1:1.0-1)\nVersion: 1:1.0-1+b1\nArchitecture: amd64",
+        ))
+        .unwrap();
+        assert_eq!("libfoo", source);
+        assert_eq!(
+            "https://metadata.ftp-master.debian.org/changelogs/main/libf/libfoo/libfoo_1.0-1_changelog",
+            expand(TEMPLATE, "main", &source, &version).unwrap().as_str()
+        );
+
+        let (source, version) = source_of(&Package::parse_test(
+            "Package: hello\nVersion: 2.10-3\nArchitecture: amd64",
+        ))
+        .unwrap();
+        assert_eq!(
+            "https://metadata.ftp-master.debian.org/changelogs/non-free/h/hello/hello_2.10-3_changelog",
+            expand(TEMPLATE, "non-free", &source, &version)
+                .unwrap()
+                .as_str()
+        );
+
+        let (source, version) = source_of(&Package::parse_test(
+            "Package: hello-data\nSource: hello\nVersion: 2.10-3\nArchitecture: all",
+        ))
+        .unwrap();
+        assert_eq!("hello", source);
+        assert_eq!("2.10-3", version.to_string());
+    }
+
+    #[test]
+    fn cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = System::cache_only_in(dir.path()).unwrap();
+        let url = expand(TEMPLATE, "main", "hello", &"2.10-3".parse().unwrap()).unwrap();
+
+        assert_eq!(None, read_cached(&system, &url).unwrap());
+
+        let path = cache_path(&system, &url);
+        assert!(path.ends_with(
+            "changelogs/metadata.ftp-master.debian.org/changelogs/main/h/hello/hello_2.10-3_changelog"
+        ));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            "hello (2.10-3) unstable; urgency=medium\n\n  * Fixes.\n\n \
+             -- Santiago Vila <sanvila@debian.org>  Sat, 18 Feb 2023 12:00:00 +0100\n",
+        )
+        .unwrap();
+
+        let changelog = read_cached(&system, &url).unwrap().unwrap();
+        assert_eq!(vec!["* Fixes."], changelog.entries[0].changes);
+    }
+}
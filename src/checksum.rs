@@ -8,9 +8,11 @@ use hex;
 use hex::FromHex;
 use sha2::Digest;
 use sha2::Sha256;
+use sha2::Sha512;
 
 pub type MD5 = [u8; 16];
 pub type SHA256 = [u8; 32];
+pub type SHA512 = [u8; 64];
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct Hashes {
@@ -57,15 +59,47 @@ pub fn parse_sha256(hash: &str) -> Result<SHA256, Error> {
     Ok(arr)
 }
 
+pub fn parse_sha512(hash: &str) -> Result<SHA512, Error> {
+    let mut arr = [0u8; 64];
+
+    let v = Vec::from_hex(hash)?;
+    ensure!(
+        arr.len() == v.len(),
+        "a sha512 checksum isn't the right length? {}",
+        hash
+    );
+
+    arr.copy_from_slice(&v);
+
+    Ok(arr)
+}
+
 // TODO: also check the md5?
-pub fn validate<R: Read>(mut file: R, checksum: Hashes) -> Result<(), Error> {
+pub fn validate<R: Read>(file: R, checksum: Hashes) -> Result<(), Error> {
+    validate_sha256(file, checksum.sha256)
+}
+
+pub fn validate_sha256<R: Read>(mut file: R, sha256: SHA256) -> Result<(), Error> {
     let mut func = Sha256::default();
     io::copy(&mut file, &mut func)?;
     let result = func.finalize();
     ensure!(
-        checksum.sha256 == result.as_slice(),
+        sha256 == result.as_slice(),
         "checksum mismatch: expected: {}, actual: {}",
-        hex::encode(checksum.sha256),
+        hex::encode(sha256),
+        hex::encode(result.as_slice())
+    );
+    Ok(())
+}
+
+pub fn validate_sha512<R: Read>(mut file: R, sha512: SHA512) -> Result<(), Error> {
+    let mut func = Sha512::default();
+    io::copy(&mut file, &mut func)?;
+    let result = func.finalize();
+    ensure!(
+        sha512[..] == result[..],
+        "sha512 mismatch: expected: {}, actual: {}",
+        hex::encode(sha512),
         hex::encode(result.as_slice())
     );
     Ok(())
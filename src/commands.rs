@@ -2,12 +2,23 @@
 
 use std::collections::HashMap;
 use std::io;
+use std::io::Write;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Error;
+use insideout::InsideOut;
 
 use crate::lists;
+use crate::parse::Package;
+use crate::parse::Status;
+use crate::parse::Version;
+use crate::policy::Available;
+use crate::policy::Origin;
+use crate::policy::Policy;
+use crate::rdeps::ReverseDependencies;
+use crate::resolve::Problem;
+use crate::resolve::Resolver;
 use crate::rfc822::RfcMapExt;
 use crate::sources_list;
 use crate::system::DownloadedList;
@@ -79,6 +90,158 @@ impl Iterator for AllBlocks {
     }
 }
 
+/// Parse every _Block_ in the _System_, reporting (to stderr) and skipping any which are invalid.
+fn valid_packages(system: &System) -> Result<Vec<Package>, Error> {
+    let mut packages = Vec::new();
+    for block in all_blocks(system)? {
+        match block?.as_pkg() {
+            Ok(package) => packages.push(package),
+            Err(e) => writeln!(io::stderr(), "Skipping an invalid package: {:?}", e)?,
+        }
+    }
+    Ok(packages)
+}
+
+/// Find every binary _Package_ in the _System_ which couldn't be installed on `arch`,
+/// and explain why.
+pub fn check_installable(system: &System, arch: &str) -> Result<Vec<(Package, Problem)>, Error> {
+    let packages = valid_packages(system)?;
+
+    let resolver = Resolver::new(&packages, arch)?;
+
+    Ok(resolver
+        .uninstallable()
+        .into_iter()
+        .map(|(package, problem)| (package.clone(), problem))
+        .collect())
+}
+
+/// Print (to stdout) every _Package_ in the _System_ which refers to `name`, and how.
+///
+/// Source _Packages_' build relationships are only included if `build` is set.
+pub fn rdepends(system: &System, name: &str, build: bool) -> Result<(), Error> {
+    let packages = valid_packages(system)?;
+
+    let index = ReverseDependencies::new(&packages);
+
+    for reference in index.of(name) {
+        if reference.field.is_build() && !build {
+            continue;
+        }
+
+        println!(
+            "{}\t{}\t{}\t{}",
+            reference.package.name,
+            reference.package.version,
+            reference.field,
+            reference.dependency
+        );
+    }
+
+    Ok(())
+}
+
+/// Every version of the binary package `name` in the _System_, and where it's available from,
+/// newest first. Versions installed according to the `dpkg` database are included.
+pub fn available_versions(system: &System, name: &str) -> Result<Vec<Available>, Error> {
+    let mut found: HashMap<Version, Vec<Origin>> = HashMap::new();
+
+    for list in system.listings()? {
+        let origin = Origin::from_list(&list);
+        for block in system.open_listing(&list)? {
+            let block = block?;
+            let map = block.as_map()?;
+            if list.listing.arch.is_none() || map.get_value("Package").one_line()? != Some(name) {
+                continue;
+            }
+            let version = map.get_value("Version").one_line_req()?.parse()?;
+            found.entry(version).or_default().push(origin.clone());
+        }
+    }
+
+    // no dpkg database means nothing is installed
+    if let Ok(status) = system.open_status() {
+        for block in status {
+            let block = block?;
+            let map = block.as_map()?;
+            if map.get_value("Package").one_line()? != Some(name) {
+                continue;
+            }
+            let installed = map
+                .get_value("Status")
+                .one_line()?
+                .map(|status| status.parse::<Status>())
+                .inside_out()?
+                .map(|status| status.is_installed())
+                .unwrap_or(false);
+            if installed {
+                let version = map.get_value("Version").one_line_req()?.parse()?;
+                found.entry(version).or_default().push(Origin::installed());
+            }
+        }
+    }
+
+    let mut ret: Vec<Available> = found
+        .into_iter()
+        .map(|(version, origins)| Available { version, origins })
+        .collect();
+    ret.sort_by(|left, right| right.version.cmp(&left.version));
+    Ok(ret)
+}
+
+/// Print (to stdout) the priorities of the versions of `name`, and the candidate,
+/// in the style of `apt-cache policy`.
+pub fn policy(system: &System, policy: &Policy, name: &str) -> Result<(), Error> {
+    let versions = available_versions(system, name)?;
+    let installed = versions
+        .iter()
+        .find(|available| available.origins.iter().any(|o| o.installed));
+    let candidate = policy.candidate(name, &versions);
+
+    let show = |available: Option<&Available>| {
+        available
+            .map(|available| available.version.to_string())
+            .unwrap_or_else(|| "(none)".to_string())
+    };
+
+    println!("{}:", name);
+    println!("  Installed: {}", show(installed));
+    println!("  Candidate: {}", show(candidate));
+    println!("  Version table:");
+
+    for available in &versions {
+        let marker = if available.origins.iter().any(|o| o.installed) {
+            "***"
+        } else {
+            "   "
+        };
+        println!(
+            " {} {} {}",
+            marker,
+            available.version,
+            policy.priority(name, &available.version, &available.origins)
+        );
+
+        for origin in &available.origins {
+            let priority = policy.priority(name, &available.version, std::slice::from_ref(origin));
+            if origin.installed {
+                println!("        {} dpkg status", priority);
+                continue;
+            }
+            println!(
+                "        {} {} {}/{} {}",
+                priority,
+                origin.site,
+                origin.archive.as_deref().unwrap_or(""),
+                origin.component.as_deref().unwrap_or(""),
+                origin.arch.as_deref().unwrap_or("source")
+            );
+        }
+    }
+
+    Ok(())
+}
+
 /// Generate the `.ninja` file (to stdout) for every package in the _System_.
 pub fn source_ninja(system: &System) -> Result<(), Error> {
     for list in system.listings()? {
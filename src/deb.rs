@@ -0,0 +1,398 @@
+//! Read `.deb` files, without `dpkg-deb`.
+//!
+//! A `.deb` is an `ar` archive containing `debian-binary` (the format version),
+//! `control.tar.*` (the _Package_'s metadata and maintainer scripts), and
+//! `data.tar.*` (the files to install), in that order.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! use fapt::deb::DebReader;
+//! let mut deb = DebReader::new(std::fs::File::open("hello_2.10-3_amd64.deb")?)?;
+//! println!("{}", deb.control()?.package.version);
+//! deb.data(|entry, _content| {
+//!     println!("{}", entry.path.display());
+//!     Ok(())
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+
+use crate::lists::Compression;
+use crate::parse::Package;
+use crate::rfc822;
+
+/// A `.deb`, being read from start to end.
+pub struct DebReader<R: Read> {
+    archive: ar::Archive<R>,
+    format_version: String,
+    read_control: bool,
+}
+
+/// The contents of `control.tar.*`.
+#[derive(Clone, Debug)]
+pub struct Control {
+    /// The parsed `control` file.
+    pub package: Package,
+    /// All of the files, including `control`, e.g. `md5sums`, `conffiles`, `postinst`,
+    /// by name (without any leading `./`).
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// A file, directory, link, etc. in the `data.tar.*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The path, relative to the root of the filesystem, without any leading `./`.
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// The permission bits, e.g. `0o755`.
+    pub mode: u32,
+    pub uid: u64,
+    pub gid: u64,
+    pub size: u64,
+    pub mtime: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink(PathBuf),
+    /// A hard link to another path in the archive, relative like `Entry::path`.
+    Hardlink(PathBuf),
+    /// Device nodes, fifos, etc.
+    Other,
+}
+
+impl<R: Read> DebReader<R> {
+    /// Start reading a `.deb`, checking it's a format we understand.
+    pub fn new(from: R) -> Result<DebReader<R>, Error> {
+        let mut archive = ar::Archive::new(from);
+
+        let mut format_version = String::new();
+        {
+            let mut member =
+                next_member(&mut archive)?.ok_or_else(|| anyhow!("empty archive: not a .deb?"))?;
+            ensure!(
+                b"debian-binary" == member.header().identifier(),
+                "first member isn't debian-binary: not a .deb?"
+            );
+            member
+                .read_to_string(&mut format_version)
+                .with_context(|| anyhow!("reading debian-binary"))?;
+        }
+
+        let format_version = format_version.trim().to_string();
+        ensure!(
+            format_version.starts_with("2."),
+            "unsupported .deb format version: {:?}",
+            format_version
+        );
+
+        Ok(DebReader {
+            archive,
+            format_version,
+            read_control: false,
+        })
+    }
+
+    /// The contents of `debian-binary`, e.g. `2.0`.
+    pub fn format_version(&self) -> &str {
+        &self.format_version
+    }
+
+    /// Read the `control.tar.*`. This must be called (at most once) before `data`.
+    pub fn control(&mut self) -> Result<Control, Error> {
+        ensure!(!self.read_control, "control has already been read");
+        self.read_control = true;
+
+        let member =
+            next_member(&mut self.archive)?.ok_or_else(|| anyhow!("no control.tar member"))?;
+        let name = member_name(&member)?;
+        let compression = tar_compression(&name, "control.tar")?;
+
+        let mut files = BTreeMap::new();
+        let mut tar = tar::Archive::new(compression.decompress(io::BufReader::new(member))?);
+        for entry in tar.entries().with_context(|| anyhow!("reading {}", name))? {
+            let mut entry = entry.with_context(|| anyhow!("reading {}", name))?;
+            if tar::EntryType::Regular != entry.header().entry_type() {
+                continue;
+            }
+            let path = relative(&entry.path()?).to_string_lossy().to_string();
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry
+                .read_to_end(&mut content)
+                .with_context(|| anyhow!("reading {} from {}", path, name))?;
+            files.insert(path, content);
+        }
+
+        let control = files
+            .get("control")
+            .ok_or_else(|| anyhow!("no control file in {}", name))?;
+        let control = str::from_utf8(control).with_context(|| anyhow!("control isn't utf-8"))?;
+        let package = Package::parse(&mut rfc822::fields_in_block(control).collect_to_map()?)
+            .with_context(|| anyhow!("parsing control file"))?;
+
+        Ok(Control { package, files })
+    }
+
+    /// Walk through the `data.tar.*`, calling `each` with every entry, and a reader
+    /// for its content (which is empty for anything but regular files).
+    ///
+    /// The `control` is skipped, if it hasn't already been read.
+    pub fn data<F>(&mut self, mut each: F) -> Result<(), Error>
+    where
+        F: FnMut(&Entry, &mut dyn Read) -> Result<(), Error>,
+    {
+        if !self.read_control {
+            self.read_control = true;
+            next_member(&mut self.archive)?.ok_or_else(|| anyhow!("no control.tar member"))?;
+        }
+
+        let member =
+            next_member(&mut self.archive)?.ok_or_else(|| anyhow!("no data.tar member"))?;
+        let name = member_name(&member)?;
+        let compression = tar_compression(&name, "data.tar")?;
+
+        let mut tar = tar::Archive::new(compression.decompress(io::BufReader::new(member))?);
+        for entry in tar.entries().with_context(|| anyhow!("reading {}", name))? {
+            let mut entry = entry.with_context(|| anyhow!("reading {}", name))?;
+            let path = relative(&entry.path()?).to_path_buf();
+            let header = entry.header();
+
+            let link = || -> Result<PathBuf, Error> {
+                Ok(entry
+                    .link_name()?
+                    .ok_or_else(|| anyhow!("link with no target: {:?}", path))?
+                    .to_path_buf())
+            };
+
+            let kind = match header.entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                tar::EntryType::Directory => EntryKind::Directory,
+                tar::EntryType::Symlink => EntryKind::Symlink(link()?),
+                tar::EntryType::Link => EntryKind::Hardlink(relative(&link()?).to_path_buf()),
+                _ => EntryKind::Other,
+            };
+
+            let info = Entry {
+                kind,
+                mode: header.mode()?,
+                uid: header.uid()?,
+                gid: header.gid()?,
+                size: header.size()?,
+                mtime: header.mtime()?,
+                path,
+            };
+
+            each(&info, &mut entry).with_context(|| anyhow!("processing {:?}", info.path))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn next_member<R: Read>(archive: &mut ar::Archive<R>) -> Result<Option<ar::Entry<'_, R>>, Error> {
+    archive
+        .next_entry()
+        .transpose()
+        .with_context(|| anyhow!("reading ar archive"))
+}
+
+fn member_name<R: Read>(member: &ar::Entry<'_, R>) -> Result<String, Error> {
+    Ok(str::from_utf8(member.header().identifier())
+        .with_context(|| anyhow!("invalid member name"))?
+        .to_string())
+}
+
+/// `data.tar`, `data.tar.xz`, etc.
+fn tar_compression(name: &str, expected: &str) -> Result<Compression, Error> {
+    let suffix = match name.strip_prefix(expected) {
+        Some(suffix) => suffix,
+        None => bail!("expected {}.*, not {:?}", expected, name),
+    };
+
+    if suffix.is_empty() {
+        return Ok(Compression::None);
+    }
+
+    Compression::from_name(suffix)
+        .filter(|c| c.suffix() == suffix)
+        .ok_or_else(|| anyhow!("unsupported compression: {:?}", name))
+}
+
+/// Strip the leading `./` (or `/`) that tarballs in `.deb`s have.
+fn relative(path: &Path) -> &Path {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io;
+    use std::io::Read;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use flate2::write::GzEncoder;
+
+    use super::DebReader;
+    use super::EntryKind;
+
+    fn tar_gz(build: impl FnOnce(&mut tar::Builder<GzEncoder<Vec<u8>>>)) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Default::default()));
+        build(&mut builder);
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn header(kind: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mode(mode);
+        header.set_size(size);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        header
+    }
+
+    pub(crate) fn file(
+        builder: &mut tar::Builder<GzEncoder<Vec<u8>>>,
+        path: &str,
+        mode: u32,
+        data: &[u8],
+    ) {
+        let mut header = header(tar::EntryType::Regular, mode, data.len() as u64);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+
+    pub(crate) fn link(
+        builder: &mut tar::Builder<GzEncoder<Vec<u8>>>,
+        kind: tar::EntryType,
+        path: &str,
+        target: &str,
+    ) {
+        let mut header = header(kind, 0o777, 0);
+        builder.append_link(&mut header, path, target).unwrap();
+    }
+
+    /// A tiny `hello` package: `usr/bin/hello`, and some links to it.
+    pub(crate) fn deb() -> Vec<u8> {
+        deb_with(|b| {
+            let mut header = header(tar::EntryType::Directory, 0o755, 0);
+            b.append_data(&mut header, "./usr/bin/", io::empty())
+                .unwrap();
+            file(b, "./usr/bin/hello", 0o755, b"\x7fELF");
+            link(b, tar::EntryType::Symlink, "./usr/bin/hi", "hello");
+            link(b, tar::EntryType::Link, "./usr/bin/hey", "./usr/bin/hello");
+        })
+    }
+
+    /// The `hello` package, with whatever `data` in its `data.tar`.
+    pub(crate) fn deb_with(data: impl FnOnce(&mut tar::Builder<GzEncoder<Vec<u8>>>)) -> Vec<u8> {
+        let control = tar_gz(|b| {
+            file(
+                b,
+                "./control",
+                0o644,
+                b"Package: hello\nVersion: 2.10-3\nArchitecture: amd64\n\
+                  Maintainer: Someone <someone@example.com>\nDescription: says hello\n",
+            );
+            file(b, "./postinst", 0o755, b"#!/bin/sh\n");
+        });
+
+        let data = tar_gz(data);
+
+        let mut ar = ar::Builder::new(Vec::new());
+        for (name, content) in &[
+            ("debian-binary", b"2.0\n".to_vec()),
+            ("control.tar.gz", control),
+            ("data.tar.gz", data),
+        ] {
+            ar.append(
+                &ar::Header::new(name.as_bytes().to_vec(), content.len() as u64),
+                &content[..],
+            )
+            .unwrap();
+        }
+        let mut ret = ar.into_inner().unwrap();
+        ret.flush().unwrap();
+        ret
+    }
+
+    #[test]
+    fn read() {
+        let deb = deb();
+        let mut reader = DebReader::new(io::Cursor::new(&deb)).unwrap();
+        assert_eq!("2.0", reader.format_version());
+
+        let control = reader.control().unwrap();
+        assert_eq!("hello", control.package.name);
+        assert_eq!("2.10-3", control.package.version.as_str());
+        assert_eq!(
+            vec!["control", "postinst"],
+            control.files.keys().collect::<Vec<_>>()
+        );
+
+        let mut seen = Vec::new();
+        reader
+            .data(|entry, content| {
+                let mut data = Vec::new();
+                content.read_to_end(&mut data)?;
+                seen.push((entry.path.clone(), entry.kind.clone(), entry.mode, data));
+                Ok(())
+            })
+            .unwrap();
+
+        let p = PathBuf::from;
+        assert_eq!(
+            vec![
+                (p("usr/bin"), EntryKind::Directory, 0o755, vec![]),
+                (
+                    p("usr/bin/hello"),
+                    EntryKind::File,
+                    0o755,
+                    b"\x7fELF".to_vec()
+                ),
+                (
+                    p("usr/bin/hi"),
+                    EntryKind::Symlink(p("hello")),
+                    0o777,
+                    vec![]
+                ),
+                (
+                    p("usr/bin/hey"),
+                    EntryKind::Hardlink(p("usr/bin/hello")),
+                    0o777,
+                    vec![]
+                ),
+            ],
+            seen
+        );
+
+        // data without control
+        let mut reader = DebReader::new(io::Cursor::new(&deb)).unwrap();
+        let mut count = 0;
+        reader
+            .data(|_, _| {
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(4, count);
+
+        assert!(DebReader::new(io::Cursor::new(b"!<arch>\n")).is_err());
+    }
+}
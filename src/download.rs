@@ -0,0 +1,382 @@
+//! Fetch the files making up binary and source packages from a mirror's `pool`,
+//! checking them against the hashes in the (signed) _Listings_.
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), anyhow::Error> {
+//! use fapt::download;
+//! # let system = fapt::system::System::cache_only()?;
+//! let deb = download::binary_file(&system, "hello", None, "amd64")?;
+//! download::download_to(&system, &[deb], ".").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use md5::Digest;
+use md5::Md5;
+use reqwest::Url;
+
+use crate::checksum;
+use crate::checksum::MD5;
+use crate::checksum::SHA256;
+use crate::checksum::SHA512;
+use crate::fetch::fetch;
+use crate::fetch::Download;
+use crate::parse::Package;
+use crate::parse::PackageType;
+use crate::parse::Version;
+use crate::rfc822::RfcMapExt;
+use crate::system::System;
+
+/// A file in a mirror's `pool`, and what it should contain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolFile {
+    pub url: Url,
+    /// The file name, without any directories.
+    pub name: String,
+    pub size: u64,
+    pub md5: Option<MD5>,
+    pub sha256: Option<SHA256>,
+    pub sha512: Option<SHA512>,
+}
+
+/// Find the `.deb` for a binary package, built for `arch` (or `all`).
+///
+/// If `version` isn't specified, the newest version is used.
+pub fn binary_file(
+    system: &System,
+    name: &str,
+    version: Option<&Version>,
+    arch: &str,
+) -> Result<PoolFile, Error> {
+    let mut best: Option<(Package, Url)> = None;
+
+    for list in system.listings()? {
+        if list.listing.arch.is_none() {
+            continue;
+        }
+
+        for block in system.open_listing(&list)? {
+            let block = block?;
+            let map = block.as_map()?;
+            let package_arch = map.get_value("Architecture").one_line()?;
+            if map.get_value("Package").one_line()? != Some(name)
+                || (package_arch != Some(arch) && package_arch != Some("all"))
+            {
+                continue;
+            }
+
+            let package = match block.as_pkg() {
+                Ok(package) => package,
+                Err(e) => {
+                    writeln!(
+                        io::stderr(),
+                        "Skipping an invalid package in {} {}: {:?}",
+                        list.release.req.mirror,
+                        list.listing.component,
+                        e
+                    )?;
+                    continue;
+                }
+            };
+            if wanted(&package, version, &best) {
+                best = Some((package, list.release.req.mirror.clone()));
+            }
+        }
+    }
+
+    let (package, mirror) = best.ok_or_else(|| not_found("binary", name, version))?;
+    pool_file(&mirror, &package)
+}
+
+/// Where the `.deb` for this binary _Package_ is, on this mirror.
+pub fn pool_file(mirror: &Url, package: &Package) -> Result<PoolFile, Error> {
+    let file = package
+        .as_bin()
+        .ok_or_else(|| anyhow!("{} is a source package", package.name))?
+        .file
+        .as_ref()
+        .ok_or_else(|| anyhow!("{} {} has no Filename", package.name, package.version))?;
+
+    Ok(PoolFile {
+        url: mirror.join(&file.name)?,
+        name: file_name(&file.name).to_string(),
+        size: file.size,
+        md5: file.md5.as_deref().map(checksum::parse_md5).transpose()?,
+        sha256: Some(checksum::parse_sha256(&file.sha256)?),
+        sha512: file
+            .sha512
+            .as_deref()
+            .map(checksum::parse_sha512)
+            .transpose()?,
+    })
+}
+
+/// Find the `.dsc`, tarballs, etc. which make up a source package.
+///
+/// `name` may also be the name of a binary package built by the source package.
+/// If `version` isn't specified, the newest version is used.
+pub fn source_files(
+    system: &System,
+    name: &str,
+    version: Option<&Version>,
+) -> Result<Vec<PoolFile>, Error> {
+    let mut exact: Option<(Package, Url)> = None;
+    let mut builds: Option<(Package, Url)> = None;
+
+    for list in system.listings()? {
+        if list.listing.arch.is_some() {
+            continue;
+        }
+
+        for block in system.open_listing(&list)? {
+            let block = block?;
+            let map = block.as_map()?;
+            let is_named = map.get_value("Package").one_line()? == Some(name);
+            let builds_named = map
+                .get("Binary")
+                .map(|lines| {
+                    lines
+                        .iter()
+                        .flat_map(|line| line.split(','))
+                        .any(|binary| binary.trim() == name)
+                })
+                .unwrap_or(false);
+
+            if !is_named && !builds_named {
+                continue;
+            }
+
+            let package = match block.as_pkg() {
+                Ok(package) => package,
+                Err(e) => {
+                    writeln!(
+                        io::stderr(),
+                        "Skipping an invalid package in {} {}: {:?}",
+                        list.release.req.mirror,
+                        list.listing.component,
+                        e
+                    )?;
+                    continue;
+                }
+            };
+            let best = if is_named { &mut exact } else { &mut builds };
+            if wanted(&package, version, best) {
+                *best = Some((package, list.release.req.mirror.clone()));
+            }
+        }
+    }
+
+    let (package, mirror) = exact
+        .or(builds)
+        .ok_or_else(|| not_found("source", name, version))?;
+
+    let src = match &package.style {
+        PackageType::Source(src) => src,
+        PackageType::Binary(_) => unreachable!("filtered out above"),
+    };
+
+    let directory = mirror.join(&format!("{}/", src.directory.trim_end_matches('/')))?;
+
+    src.files
+        .iter()
+        .map(|archive| {
+            Ok(PoolFile {
+                url: directory.join(&archive.name)?,
+                name: archive.name.to_string(),
+                size: archive.size,
+                md5: Some(archive.md5),
+                sha256: archive.sha256,
+                sha512: archive.sha512,
+            })
+        })
+        .collect()
+}
+
+/// Download these files into `dest`, and check they are what we expected.
+///
+/// Files which are already present, and correct, are not downloaded again.
+/// Files which turn out to be wrong are deleted.
+pub async fn download_to<P: AsRef<Path>>(
+    system: &System,
+    files: &[PoolFile],
+    dest: P,
+) -> Result<Vec<PathBuf>, Error> {
+    let dest = dest.as_ref();
+    let mut downloads = Vec::with_capacity(files.len());
+    let mut ret = Vec::with_capacity(files.len());
+
+    for file in files {
+        ensure!(
+            !file.name.contains('/') && !file.name.starts_with('.'),
+            "refusing to write to suspicious file name: {:?}",
+            file.name
+        );
+
+        let path = dest.join(&file.name);
+        if !path.exists() || verify(&path, file).is_err() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            downloads.push(Download::from_to(file.url.clone(), &path));
+        }
+        ret.push(path);
+    }
+
+    fetch(system.client.clone(), downloads).await?;
+
+    for (path, file) in ret.iter().zip(files) {
+        if let Err(e) = verify(path, file) {
+            fs::remove_file(path)?;
+            return Err(e.context(anyhow!("verifying {} (now deleted)", file.url)));
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Check a file is the right size, and matches all the hashes we have for it.
+pub fn verify<P: AsRef<Path>>(path: P, file: &PoolFile) -> Result<(), Error> {
+    verify_hashes(path.as_ref(), file.size, file.md5, file.sha256, file.sha512)
+}
+
+/// Check a file listed in e.g. a `.dsc` or `.changes` is in `dir`, with the right size and hashes.
+pub(crate) fn verify_listed(
+    dir: &Path,
+    name: &str,
+    size: u64,
+    md5: Option<MD5>,
+    sha256: Option<SHA256>,
+    sha512: Option<SHA512>,
+) -> Result<(), Error> {
+    ensure!(!name.contains('/'), "suspicious file name: {:?}", name);
+    verify_hashes(&dir.join(name), size, md5, sha256, sha512)
+        .with_context(|| anyhow!("verifying {:?}", name))
+}
+
+/// As `verify`, for a file described in some other way, e.g. in a `.dsc`.
+///
+/// The md5 is only checked if there's nothing better.
+pub(crate) fn verify_hashes(
+    path: &Path,
+    expected: u64,
+    md5: Option<MD5>,
+    sha256: Option<SHA256>,
+    sha512: Option<SHA512>,
+) -> Result<(), Error> {
+    let open = || fs::File::open(path).with_context(|| anyhow!("opening {:?}", path));
+
+    let size = open()?.metadata()?.len();
+    ensure!(
+        expected == size,
+        "size mismatch: expected: {}, actual: {}",
+        expected,
+        size
+    );
+
+    if let Some(sha512) = sha512 {
+        checksum::validate_sha512(open()?, sha512)?;
+    }
+
+    if let Some(sha256) = sha256 {
+        checksum::validate_sha256(open()?, sha256)?;
+    }
+
+    match (md5, sha256, sha512) {
+        (_, Some(_), _) | (_, _, Some(_)) => (),
+        (Some(md5), None, None) => {
+            let mut func = Md5::default();
+            io::copy(&mut open()?, &mut func)?;
+            let result = func.finalize();
+            ensure!(
+                md5[..] == result[..],
+                "md5 mismatch: expected: {}, actual: {}",
+                hex::encode(md5),
+                hex::encode(result.as_slice())
+            );
+        }
+        (None, None, None) => bail!("no hashes available to check {:?}", path),
+    }
+
+    Ok(())
+}
+
+/// Is this package what was asked for, and better than what we've already found?
+fn wanted(package: &Package, version: Option<&Version>, best: &Option<(Package, Url)>) -> bool {
+    match version {
+        Some(version) => *version == package.version && best.is_none(),
+        None => best
+            .as_ref()
+            .map(|(best, _)| package.version > best.version)
+            .unwrap_or(true),
+    }
+}
+
+fn not_found(kind: &str, name: &str, version: Option<&Version>) -> Error {
+    match version {
+        Some(version) => anyhow!("{} package {} {} not found", kind, name, version),
+        None => anyhow!("{} package {} not found", kind, name),
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sha2::Digest;
+    use sha2::Sha256;
+
+    use super::verify;
+    use super::PoolFile;
+
+    #[test]
+    fn verify_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo_1.0.dsc");
+        fs::write(&path, b"hello\n").unwrap();
+
+        let mut sha256 = [0u8; 32];
+        sha256.copy_from_slice(&Sha256::digest(b"hello\n"));
+
+        let mut file = PoolFile {
+            url: "http://example.com/pool/main/f/foo/foo_1.0.dsc"
+                .parse()
+                .unwrap(),
+            name: "foo_1.0.dsc".to_string(),
+            size: 6,
+            md5: None,
+            sha256: Some(sha256),
+            sha512: None,
+        };
+        verify(&path, &file).unwrap();
+
+        file.size = 7;
+        assert!(verify(&path, &file).is_err());
+
+        file.size = 6;
+        file.sha256.as_mut().unwrap()[0] ^= 1;
+        assert!(verify(&path, &file).is_err());
+
+        file.sha256 = None;
+        file.md5 = Some(crate::checksum::parse_md5("b1946ac92492d2347c6235b4d2611184").unwrap());
+        verify(&path, &file).unwrap();
+
+        file.md5 = None;
+        assert!(verify(&path, &file).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Modify a `dpkg` database, e.g. `/var/lib/dpkg`: the `status` file, and `info/*`.
+//!
+//! Nothing is written until the _Database_ is committed, and every file is replaced
+//! atomically, so a reader (including `dpkg`) sees either the old or the new version.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! use fapt::dpkg::Database;
+//! let mut db = Database::open("/srv/image/var/lib/dpkg")?;
+//! db.set_status("hello", "install ok installed".parse()?)?;
+//! db.commit()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use insideout::InsideOut;
+use tempfile_fast::PersistableTempFile;
+
+use crate::parse::Status;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// A `dpkg` database directory, with pending changes.
+pub struct Database {
+    dir: PathBuf,
+    /// By `name`, or `name:arch` for `Multi-Arch: same` packages, as `dpkg` does.
+    entries: BTreeMap<String, Entry>,
+    /// `info` files, by their name, e.g. `hello.list`, to write on commit.
+    info: BTreeMap<String, Vec<u8>>,
+}
+
+struct Entry {
+    status: Status,
+    /// All the other fields of the stanza, in their original order.
+    fields: String,
+}
+
+impl Database {
+    /// Load the `status` file from this directory, if there is one.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Database, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut entries = BTreeMap::new();
+
+        let path = dir.join("status");
+        if path.exists() {
+            let file = fs::File::open(&path).with_context(|| anyhow!("opening {:?}", path))?;
+            for block in rfc822::Blocks::new(file, "status".to_string()) {
+                let block = block?;
+                let (key, status, fields) =
+                    Entry::parse(&block).with_context(|| anyhow!("reading {:?}", path))?;
+                let status =
+                    status.ok_or_else(|| anyhow!("no Status for {} in {:?}", key, path))?;
+                entries.insert(key, Entry { status, fields });
+            }
+        }
+
+        Ok(Database {
+            dir,
+            entries,
+            info: BTreeMap::new(),
+        })
+    }
+
+    /// The names of all the packages in the database, `name:arch` for `Multi-Arch: same`.
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|k| k.as_str())
+    }
+
+    /// The `Status` of a package, e.g. `install ok installed`.
+    pub fn status(&self, package: &str) -> Option<Status> {
+        self.entries.get(package).map(|e| e.status)
+    }
+
+    /// Add (or replace) a package, from the `control` file of its `.deb`, returning its name.
+    pub fn add(&mut self, control: &str, status: Status) -> Result<String, Error> {
+        let (key, _, fields) = Entry::parse(control)?;
+        self.entries.insert(key.clone(), Entry { status, fields });
+        Ok(key)
+    }
+
+    /// Change the `Status` of a package which is already in the database.
+    pub fn set_status(&mut self, package: &str, status: Status) -> Result<(), Error> {
+        self.entries
+            .get_mut(package)
+            .ok_or_else(|| anyhow!("{} isn't in the database", package))?
+            .status = status;
+        Ok(())
+    }
+
+    /// Forget a package entirely, e.g. after it's purged. Its `info` files are left alone.
+    pub fn remove(&mut self, package: &str) -> bool {
+        self.entries.remove(package).is_some()
+    }
+
+    /// Record the files a package owns, as `info/<package>.list`.
+    ///
+    /// The paths are relative to the root, as in the `.deb`; they're written absolute.
+    pub fn set_files<P: AsRef<Path>>(&mut self, package: &str, paths: &[P]) {
+        let mut list = b"/.\n".to_vec();
+        for path in paths {
+            list.extend(format!("/{}\n", path.as_ref().display()).bytes());
+        }
+        self.info.insert(format!("{}.list", package), list);
+    }
+
+    /// Record the checksums of a package's files, i.e. the `md5sums` from its `.deb`.
+    pub fn set_md5sums(&mut self, package: &str, md5sums: &[u8]) {
+        self.set_info(package, "md5sums", md5sums);
+    }
+
+    /// Record any other `info` file for a package, e.g. `conffiles` or `postinst`.
+    pub fn set_info(&mut self, package: &str, kind: &str, content: &[u8]) {
+        self.info
+            .insert(format!("{}.{}", package, kind), content.to_vec());
+    }
+
+    /// Write all of the changes: the `info` files first, then the `status`.
+    ///
+    /// The previous `status` is kept as `status-old`, as `dpkg` does.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        let info = self.dir.join("info");
+        fs::create_dir_all(&info).with_context(|| anyhow!("creating {:?}", info))?;
+
+        for (name, content) in &self.info {
+            ensure!(
+                !name.contains('/') && !name.starts_with('.'),
+                "suspicious info file name: {:?}",
+                name
+            );
+            replace(&info.join(name), |out| out.write_all(content))?;
+        }
+        self.info.clear();
+
+        let status = self.dir.join("status");
+        if status.exists() {
+            fs::copy(&status, self.dir.join("status-old"))
+                .with_context(|| anyhow!("backing up {:?}", status))?;
+        }
+
+        replace(&status, |out| {
+            for entry in self.entries.values() {
+                out.write_all(entry.render().as_bytes())?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Entry {
+    /// The key, `Status` (if any), and other fields, of a stanza.
+    fn parse(block: &str) -> Result<(String, Option<Status>, String), Error> {
+        let map = rfc822::fields_in_block(block).collect_to_map()?;
+        let name = map.get_value("Package").one_line_req()?;
+        let key = match map.get_value("Multi-Arch").one_line()? {
+            Some("same") => format!("{}:{}", name, map.get_value("Architecture").one_line_req()?),
+            _ => name.to_string(),
+        };
+        let status = map
+            .get_value("Status")
+            .one_line()?
+            .map(|status| status.parse())
+            .inside_out()?;
+
+        let mut fields = String::with_capacity(block.len());
+        for line in block.trim_end().lines() {
+            if !line.starts_with("Status:") {
+                fields.push_str(line);
+                fields.push('\n');
+            }
+        }
+
+        Ok((key, status, fields))
+    }
+
+    /// The stanza, with the `Status` after the `Package` field, where `dpkg` puts it.
+    fn render(&self) -> String {
+        let mut ret = String::with_capacity(self.fields.len() + 64);
+        for line in self.fields.lines() {
+            ret.push_str(line);
+            ret.push('\n');
+            if line.starts_with("Package:") {
+                ret.push_str(&format!("Status: {}\n", self.status));
+            }
+        }
+        ret
+    }
+}
+
+/// Atomically replace a file with whatever `write` produces.
+fn replace<F>(path: &Path, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut io::BufWriter<&mut PersistableTempFile>) -> io::Result<()>,
+{
+    let parent = path
+        .parent()
+        .ok_or_else(|| anyhow!("path must have parent"))?;
+    let mut tmp = PersistableTempFile::new_in(parent)
+        .with_context(|| anyhow!("couldn't create temporary file in {:?}", parent))?;
+
+    {
+        let mut out = io::BufWriter::new(&mut tmp);
+        write(&mut out).with_context(|| anyhow!("writing {:?}", path))?;
+        out.flush()?;
+    }
+
+    tmp.persist_by_rename(path)
+        .map_err(|e| e.error)
+        .with_context(|| anyhow!("replacing {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Database;
+    use crate::parse::State;
+    use crate::parse::Status;
+    use crate::system::System;
+
+    fn status(s: &str) -> Status {
+        s.parse().unwrap()
+    }
+
+    const CONTROL: &str = "Package: libc6\nVersion: 2.36-9\nArchitecture: amd64\n\
+        Multi-Arch: same\nMaintainer: Someone <someone@example.com>\nDescription: libc\n";
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(0, db.packages().count());
+        assert_eq!(
+            "libc6:amd64",
+            db.add(CONTROL, status("install ok unpacked")).unwrap()
+        );
+        db.add(
+            &CONTROL
+                .replace("libc6", "base-files")
+                .replace("Multi-Arch: same\n", ""),
+            status("install ok installed"),
+        )
+        .unwrap();
+        db.set_files("libc6:amd64", &["lib", "lib/libc.so.6"]);
+        db.set_md5sums(
+            "libc6:amd64",
+            b"00000000000000000000000000000000  lib/libc.so.6\n",
+        );
+        db.commit().unwrap();
+
+        assert_eq!(
+            "/.\n/lib\n/lib/libc.so.6\n",
+            fs::read_to_string(dir.path().join("info/libc6:amd64.list")).unwrap()
+        );
+        assert!(dir.path().join("info/libc6:amd64.md5sums").exists());
+
+        let mut db = Database::open(dir.path()).unwrap();
+        assert_eq!(
+            vec!["base-files", "libc6:amd64"],
+            db.packages().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(status("install ok unpacked")),
+            db.status("libc6:amd64")
+        );
+        db.set_status("libc6:amd64", status("install ok installed"))
+            .unwrap();
+        assert!(db
+            .set_status("libc6", status("install ok installed"))
+            .is_err());
+        db.commit().unwrap();
+        assert!(dir.path().join("status-old").exists());
+
+        let mut system = System::cache_only_in(dir.path().join("lists")).unwrap();
+        system.set_dpkg_database(dir.path());
+        let packages = system
+            .open_status()
+            .unwrap()
+            .map(|block| block.unwrap().as_pkg().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(2, packages.len());
+        assert_eq!(
+            Some(State::Installed),
+            packages[1].as_bin().unwrap().status.map(|s| s.state)
+        );
+    }
+}
@@ -5,13 +5,27 @@
 #[macro_use]
 extern crate nom;
 
+pub mod bootstrap;
+pub mod build_deps;
+pub mod changelog;
 mod checksum;
 pub mod commands;
+pub mod deb;
+pub mod download;
+pub mod dpkg;
 mod fetch;
 mod lists;
 pub mod parse;
+mod pdiff;
+pub mod policy;
+pub mod rdeps;
 mod release;
+pub mod resolve;
 pub mod rfc822;
 mod signing;
 pub mod sources_list;
 pub mod system;
+pub mod upgradable;
+
+pub use crate::release::parse_release;
+pub use crate::release::ReleaseFile;
@@ -1,5 +1,6 @@
 use std::fs;
 use std::io;
+use std::io::BufRead;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
@@ -12,43 +13,102 @@ use anyhow::Context;
 use anyhow::Error;
 use flate2::bufread::GzDecoder;
 use hex;
+use insideout::InsideOut;
 use reqwest::Client;
 use reqwest::Url;
 use tempfile_fast::PersistableTempFile;
+use tokio::task::JoinSet;
 
 use crate::checksum;
 use crate::checksum::Hashes;
 use crate::fetch;
+use crate::pdiff;
 use crate::release::Release;
 use crate::release::ReleaseContent;
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Compression {
     None,
     Gz,
+    Xz,
+    Bz2,
+    Lzma,
+    Zstd,
 }
 
 impl Compression {
-    fn suffix(&self) -> &'static str {
+    const COMPRESSED: [Compression; 5] = [
+        Compression::Gz,
+        Compression::Xz,
+        Compression::Bz2,
+        Compression::Lzma,
+        Compression::Zstd,
+    ];
+
+    pub fn suffix(&self) -> &'static str {
         use self::Compression::*;
         match *self {
             None => "",
             Gz => ".gz",
+            Xz => ".xz",
+            Bz2 => ".bz2",
+            Lzma => ".lzma",
+            Zstd => ".zst",
         }
     }
+
+    /// The compression indicated by the end of a file name, e.g. `data.tar.xz`.
+    pub fn from_name(name: &str) -> Option<Compression> {
+        Compression::COMPRESSED
+            .iter()
+            .find(|c| name.ends_with(c.suffix()))
+            .copied()
+    }
+
+    /// Wrap a reader, such that reading from the result produces the decompressed data.
+    pub fn decompress<'r, R: BufRead + 'r>(&self, from: R) -> Result<Box<dyn Read + 'r>, Error> {
+        use self::Compression::*;
+        Ok(match *self {
+            None => Box::new(from),
+            Gz => Box::new(GzDecoder::new(from)),
+            Xz => Box::new(lzma_rust2::XzReader::new(from, true)),
+            Bz2 => Box::new(bzip2::bufread::MultiBzDecoder::new(from)),
+            Lzma => Box::new(
+                lzma_rust2::LzmaReader::new_mem_limit(from, u32::MAX, Option::None)
+                    .with_context(|| anyhow!("reading lzma header"))?,
+            ),
+            Zstd => Box::new(
+                ruzstd::decoding::StreamingDecoder::new(from)
+                    .map_err(|e| anyhow!("reading zstd header: {}", e))?,
+            ),
+        })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct DownloadableListing {
     pub url: Url,
     pub codec: Compression,
     pub compressed_hashes: Hashes,
-    pub decompressed_hashes: Hashes,
+    /// Missing if the _Release_ only lists the compressed file.
+    pub decompressed_hashes: Option<Hashes>,
+    pub diff_index: Option<DiffIndexLocation>,
+}
+
+/// Where to find the `.diff/Index` for a _Listing_, if the _Release_ has one.
+#[derive(Clone, Debug)]
+pub struct DiffIndexLocation {
+    pub url: Url,
+    pub hashes: Hashes,
 }
 
 impl DownloadableListing {
     pub fn local_name(&self) -> String {
-        hex::encode(self.decompressed_hashes.sha256)
+        hex::encode(
+            self.decompressed_hashes
+                .unwrap_or(self.compressed_hashes)
+                .sha256,
+        )
     }
 }
 
@@ -77,21 +137,63 @@ pub async fn download_files<P: AsRef<Path>>(
         .tempdir_in(&lists_dir)
         .with_context(|| anyhow!("creating temporary directory"))?;
 
-    let downloads: Vec<fetch::Download> = lists
-        .iter()
-        .filter_map(|list| {
-            let local_name = list.local_name();
+    let mut downloads = Vec::with_capacity(lists.len());
+    let mut patches = JoinSet::new();
 
-            if lists_dir.as_ref().join(&local_name).exists() {
-                None
-            } else {
-                Some(fetch::Download::from_to(
-                    list.url.clone(),
-                    temp_dir.as_ref().join(local_name),
-                ))
+    for (idx, list) in lists.iter().enumerate() {
+        let local_name = list.local_name();
+
+        if lists_dir.as_ref().join(&local_name).exists() {
+            continue;
+        }
+
+        if list.diff_index.is_some() {
+            let (client, lists_dir, temp_dir, list) = (
+                client.clone(),
+                lists_dir.as_ref().to_path_buf(),
+                temp_dir.path().to_path_buf(),
+                list.clone(),
+            );
+            patches.spawn(async move {
+                (
+                    idx,
+                    pdiff::update(&client, &lists_dir, &temp_dir, &list).await,
+                )
+            });
+            continue;
+        }
+
+        downloads.push(fetch::Download::from_to(
+            list.url.clone(),
+            temp_dir.as_ref().join(local_name),
+        ));
+    }
+
+    let mut patched = 0;
+    while let Some(res) = patches.join_next().await {
+        let (idx, res) = res?;
+        let list = &lists[idx];
+        match res {
+            Ok(true) => {
+                patched += 1;
+                continue;
             }
-        })
-        .collect();
+            Ok(false) => (),
+            Err(e) => {
+                writeln!(
+                    io::stderr(),
+                    "Patching {} failed, downloading it instead: {:?}",
+                    list.url,
+                    e
+                )?;
+            }
+        }
+
+        downloads.push(fetch::Download::from_to(
+            list.url.clone(),
+            temp_dir.as_ref().join(list.local_name()),
+        ));
+    }
 
     let updated = fetch::fetch(client.clone(), downloads)
         .await
@@ -101,11 +203,12 @@ pub async fn download_files<P: AsRef<Path>>(
         store_list_item(&list, &temp_dir, &lists_dir)?;
     }
 
-    Ok(updated
-        .iter()
-        .filter(|was_updated| **was_updated)
-        .collect::<Vec<_>>()
-        .len())
+    Ok(patched
+        + updated
+            .iter()
+            .filter(|was_updated| **was_updated)
+            .collect::<Vec<_>>()
+            .len())
 }
 
 fn store_list_item<P: AsRef<Path>, Q: AsRef<Path>>(
@@ -128,13 +231,18 @@ fn store_list_item<P: AsRef<Path>, Q: AsRef<Path>>(
 
     match list.codec {
         Compression::None => fs::rename(temp_path, destination_path)?,
-        Compression::Gz => {
+        codec => {
             temp.seek(SeekFrom::Start(0))?;
             let mut uncompressed_temp = PersistableTempFile::new_in(&lists_dir)
                 .with_context(|| anyhow!("making temporary file in {:?}", lists_dir.as_ref()))?;
 
-            decompress_gz(temp, &mut uncompressed_temp, list.decompressed_hashes)
-                .with_context(|| anyhow!("decomressing {:?}", temp_path))?;
+            decompress(
+                codec,
+                temp,
+                &mut uncompressed_temp,
+                list.decompressed_hashes,
+            )
+            .with_context(|| anyhow!("decomressing {:?}", temp_path))?;
 
             uncompressed_temp
                 .persist_by_rename(destination_path)
@@ -146,23 +254,27 @@ fn store_list_item<P: AsRef<Path>, Q: AsRef<Path>>(
     Ok(())
 }
 
-fn decompress_gz<R: Read, F: Read + Write + Seek>(
+fn decompress<R: Read, F: Read + Write + Seek>(
+    codec: Compression,
     mut compressed: R,
     mut uncompressed: F,
-    decompressed_hashes: Hashes,
+    decompressed_hashes: Option<Hashes>,
 ) -> Result<(), Error> {
     io::copy(
-        &mut GzDecoder::new(io::BufReader::new(&mut compressed)),
+        &mut codec.decompress(io::BufReader::new(&mut compressed))?,
         &mut uncompressed,
     )
     .with_context(|| anyhow!("decomressing"))?;
 
-    uncompressed
-        .seek(SeekFrom::Start(0))
-        .with_context(|| anyhow!("rewinding"))?;
+    // Without a hash for the decompressed file, the compressed one (already checked) has to do.
+    if let Some(decompressed_hashes) = decompressed_hashes {
+        uncompressed
+            .seek(SeekFrom::Start(0))
+            .with_context(|| anyhow!("rewinding"))?;
 
-    checksum::validate(&mut uncompressed, decompressed_hashes)
-        .with_context(|| anyhow!("validating decompressed file"))?;
+        checksum::validate(&mut uncompressed, decompressed_hashes)
+            .with_context(|| anyhow!("validating decompressed file"))?;
+    }
 
     Ok(())
 }
@@ -257,38 +369,164 @@ pub fn find_file(
 
     let base = format!("{}/{}/{}", listing.component, directory, listing.name);
 
-    let gz_name = format!("{}{}", base, Compression::Gz.suffix());
-
-    let mut gz_hashes = None;
-    let mut raw_hashes = None;
-
-    for content in contents {
-        if content.name == base {
-            raw_hashes = Some(content.hashes);
-        } else if content.name == gz_name {
-            gz_hashes = Some(content.hashes);
-        }
-    }
+    // Many archives only list the compressed versions.
+    let raw = contents.iter().find(|content| content.name == base);
 
-    let raw_hashes = raw_hashes.ok_or_else(|| anyhow!("file {:?} not found in release", base))?;
+    // The uncompressed file is often listed in the release, but not actually present
+    // on the mirror, so we only fall back to it if there's no compressed version at all.
+    let (codec, compressed) = Compression::COMPRESSED
+        .iter()
+        .filter_map(|codec| {
+            let name = format!("{}{}", base, codec.suffix());
+            contents
+                .iter()
+                .find(|content| content.name == name)
+                .map(|content| (*codec, content))
+        })
+        .min_by_key(|(_, content)| content.len)
+        .or_else(|| raw.map(|raw| (Compression::None, raw)))
+        .ok_or_else(|| anyhow!("file {:?} not found in release", base))?;
 
     let url = base_url.join(&if acquire_by_hash {
         format!(
             "{}/{}/by-hash/SHA256/{}",
             listing.component,
             directory,
-            hex::encode(gz_hashes.unwrap_or(raw_hashes).sha256)
+            hex::encode(compressed.hashes.sha256)
         )
     } else {
-        gz_hashes.map(|_| gz_name).unwrap_or(base)
+        compressed.name.to_string()
     })?;
 
+    let diff_index_name = format!("{}.diff/Index", base);
+    let diff_index = contents
+        .iter()
+        .find(|content| content.name == diff_index_name)
+        .map(|content| -> Result<DiffIndexLocation, Error> {
+            Ok(DiffIndexLocation {
+                url: base_url.join(&diff_index_name)?,
+                hashes: content.hashes,
+            })
+        })
+        .inside_out()?;
+
     Ok(DownloadableListing {
         url,
-        codec: gz_hashes
-            .map(|_| Compression::Gz)
-            .unwrap_or(Compression::None),
-        compressed_hashes: gz_hashes.unwrap_or(raw_hashes),
-        decompressed_hashes: raw_hashes,
+        codec,
+        compressed_hashes: compressed.hashes,
+        decompressed_hashes: raw.map(|raw| raw.hashes),
+        diff_index,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::Read;
+
+    use reqwest::Url;
+
+    use super::find_file;
+    use super::Compression;
+    use super::Listing;
+    use crate::checksum::Hashes;
+    use crate::release::ReleaseContent;
+
+    fn content(name: &str, len: u64, fill: u8) -> ReleaseContent {
+        ReleaseContent {
+            len,
+            name: name.to_string(),
+            hashes: Hashes {
+                md5: [fill; 16],
+                sha256: [fill; 32],
+            },
+        }
+    }
+
+    #[test]
+    fn smallest_compressed() {
+        let contents = vec![
+            content("main/binary-amd64/Packages", 1000, 0),
+            content("main/binary-amd64/Packages.gz", 300, 1),
+            content("main/binary-amd64/Packages.xz", 200, 2),
+            content("main/binary-amd64/Packages.bz2", 250, 3),
+        ];
+
+        let listing = Listing {
+            component: "main".to_string(),
+            arch: Some("amd64".to_string()),
+            directory: "binary".to_string(),
+            name: "Packages".to_string(),
+        };
+
+        let base = Url::parse("http://foo/debian/dists/sid/").unwrap();
+        let found = find_file(&base, &contents, false, &listing).unwrap();
+        assert_eq!(Compression::Xz, found.codec);
+        assert_eq!(
+            "http://foo/debian/dists/sid/main/binary-amd64/Packages.xz",
+            found.url.as_str()
+        );
+        assert_eq!([2; 32], found.compressed_hashes.sha256);
+        assert_eq!([0; 32], found.decompressed_hashes.unwrap().sha256);
+
+        let found = find_file(&base, &contents[..1], false, &listing).unwrap();
+        assert_eq!(Compression::None, found.codec);
+    }
+
+    #[test]
+    fn only_compressed() {
+        let contents = vec![
+            content("main/binary-amd64/Packages.gz", 300, 1),
+            content("main/binary-amd64/Packages.xz", 200, 2),
+        ];
+
+        let listing = Listing {
+            component: "main".to_string(),
+            arch: Some("amd64".to_string()),
+            directory: "binary".to_string(),
+            name: "Packages".to_string(),
+        };
+
+        let base = Url::parse("http://foo/debian/dists/sid/").unwrap();
+        let found = find_file(&base, &contents, false, &listing).unwrap();
+        assert_eq!(Compression::Xz, found.codec);
+        assert_eq!(None, found.decompressed_hashes);
+        assert_eq!(hex::encode([2; 32]), found.local_name());
+
+        assert!(find_file(&base, &[], false, &listing).is_err());
+    }
+
+    #[test]
+    fn decompress_all() {
+        for (codec, data) in &[
+            (
+                Compression::Gz,
+                &include_bytes!("../tests/compressed/Packages.gz")[..],
+            ),
+            (
+                Compression::Xz,
+                &include_bytes!("../tests/compressed/Packages.xz")[..],
+            ),
+            (
+                Compression::Bz2,
+                &include_bytes!("../tests/compressed/Packages.bz2")[..],
+            ),
+            (
+                Compression::Lzma,
+                &include_bytes!("../tests/compressed/Packages.lzma")[..],
+            ),
+            (
+                Compression::Zstd,
+                &include_bytes!("../tests/compressed/Packages.zst")[..],
+            ),
+        ] {
+            let mut out = String::new();
+            codec
+                .decompress(io::Cursor::new(data))
+                .unwrap()
+                .read_to_string(&mut out)
+                .unwrap();
+            assert_eq!("Package: foo\nVersion: 1\n", out, "{:?}", codec);
+        }
+    }
+}
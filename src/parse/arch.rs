@@ -1,40 +1,127 @@
 use std::collections::HashSet;
+use std::fmt;
 use std::str::FromStr;
 
+use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Error;
 
-/// An architecture specifier, such as `amd64`, or `linux-any`.
-///
-/// `any` and `all` are currently both represented by an absence of specifier.
-/// This is likely a flaw.
+/// An architecture specifier, such as `amd64`, `all`, or `linux-any`.
 #[derive(Copy, Clone, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
-pub struct Arch {
-    kernel: Option<Kernel>,
-    cpu: Option<Cpu>,
-    boogered: bool,
+pub enum Arch {
+    /// `all`: the package is architecture independent.
+    All,
+    /// `native`, as in a `foo:native` build dependency: whatever the build architecture is.
+    Native,
+    /// A concrete architecture, e.g. `amd64`, or a wildcard, e.g. `any`, `linux-any`, `any-arm64`.
+    Tuple(Tuple),
 }
 
+/// The dpkg-architecture "tuple" an architecture name stands for.
+///
+/// A `None` part is a wildcard (`any`); a concrete architecture has all four parts.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialOrd, Ord, PartialEq, Eq)]
+pub struct Tuple {
+    pub abi: Option<Abi>,
+    pub libc: Option<Libc>,
+    pub os: Option<Os>,
+    pub cpu: Option<Cpu>,
+}
+
+/// dpkg's `tupletable`, mapping tuples to the names people actually use.
+///
+/// `<cpu>` stands for any cpu from the `cputable`.
+const TUPLE_TABLE: &[(&str, &str)] = &[
+    ("eabi-uclibc-linux-arm", "uclibc-linux-armel"),
+    ("base-uclibc-linux-<cpu>", "uclibc-linux-<cpu>"),
+    ("eabihf-musl-linux-arm", "musl-linux-armhf"),
+    ("base-musl-linux-<cpu>", "musl-linux-<cpu>"),
+    ("ilp32-gnu-linux-arm64", "arm64ilp32"),
+    ("eabihf-gnu-linux-arm", "armhf"),
+    ("eabi-gnu-linux-arm", "armel"),
+    ("abin32-gnu-linux-mips64r6el", "mipsn32r6el"),
+    ("abin32-gnu-linux-mips64r6", "mipsn32r6"),
+    ("abin32-gnu-linux-mips64el", "mipsn32el"),
+    ("abin32-gnu-linux-mips64", "mipsn32"),
+    ("abi64-gnu-linux-mips64r6el", "mips64r6el"),
+    ("abi64-gnu-linux-mips64r6", "mips64r6"),
+    ("abi64-gnu-linux-mips64el", "mips64el"),
+    ("abi64-gnu-linux-mips64", "mips64"),
+    ("spe-gnu-linux-powerpc", "powerpcspe"),
+    ("x32-gnu-linux-amd64", "x32"),
+    ("base-gnu-linux-<cpu>", "<cpu>"),
+    ("eabihf-gnu-kfreebsd-arm", "kfreebsd-armhf"),
+    ("base-gnu-kfreebsd-<cpu>", "kfreebsd-<cpu>"),
+    ("base-gnu-knetbsd-<cpu>", "knetbsd-<cpu>"),
+    ("base-gnu-kopensolaris-<cpu>", "kopensolaris-<cpu>"),
+    ("base-gnu-hurd-<cpu>", "hurd-<cpu>"),
+    ("base-bsd-dragonflybsd-<cpu>", "dragonflybsd-<cpu>"),
+    ("base-bsd-freebsd-<cpu>", "freebsd-<cpu>"),
+    ("base-bsd-openbsd-<cpu>", "openbsd-<cpu>"),
+    ("base-bsd-netbsd-<cpu>", "netbsd-<cpu>"),
+    ("base-bsd-darwin-<cpu>", "darwin-<cpu>"),
+    ("base-sysv-aix-<cpu>", "aix-<cpu>"),
+    ("base-sysv-solaris-<cpu>", "solaris-<cpu>"),
+    ("eabi-uclibc-uclinux-arm", "uclinux-armel"),
+    ("base-uclibc-uclinux-<cpu>", "uclinux-<cpu>"),
+    ("base-tos-mint-m68k", "mint-m68k"),
+];
+
 impl Arch {
+    /// Is this the `any` wildcard, which matches every concrete architecture?
     pub fn is_any(&self) -> bool {
-        self.kernel.is_none() && self.cpu.is_none()
+        match self {
+            Arch::Tuple(tuple) => {
+                tuple.abi.is_none()
+                    && tuple.libc.is_none()
+                    && tuple.os.is_none()
+                    && tuple.cpu.is_none()
+            }
+            _ => false,
+        }
     }
 
-    pub fn boogered() -> Arch {
-        Arch {
-            kernel: None,
-            cpu: None,
-            boogered: true,
+    /// Is this a real architecture, e.g. `amd64`, rather than `all` or a wildcard?
+    pub fn is_concrete(&self) -> bool {
+        match self {
+            Arch::Tuple(tuple) => {
+                tuple.abi.is_some()
+                    && tuple.libc.is_some()
+                    && tuple.os.is_some()
+                    && tuple.cpu.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Does this specifier, e.g. `linux-any`, cover this concrete architecture, e.g. `amd64`?
+    ///
+    /// This follows `dpkg-architecture --is`: each part of the tuple must be equal, or a
+    /// wildcard. `all` only matches `all`, and `native` must be resolved by the caller,
+    /// so never matches.
+    pub fn matches(&self, concrete: &Arch) -> bool {
+        match (self, concrete) {
+            (Arch::All, Arch::All) => true,
+            (Arch::Tuple(wild), Arch::Tuple(concrete)) => {
+                part_matches(wild.abi, concrete.abi)
+                    && part_matches(wild.libc, concrete.libc)
+                    && part_matches(wild.os, concrete.os)
+                    && part_matches(wild.cpu, concrete.cpu)
+            }
+            _ => false,
         }
     }
 }
 
+fn part_matches<T: PartialEq>(wild: Option<T>, concrete: Option<T>) -> bool {
+    wild.is_none() || wild == concrete
+}
+
 impl FromStr for Arch {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Arch, Error> {
-        // TODO: what *are* we going to do about any vs. all?
-
         // > Specifying only any indicates that the source package isn’t dependent on any
         // particular architecture and should compile fine on any one. The produced binary
         // package(s) will be specific to whatever the current build architecture is.
@@ -46,70 +133,150 @@ impl FromStr for Arch {
         // particular architecture. The set of produced binary packages will include at
         // least one architecture-dependent package and one architecture-independent package.
 
-        if "any" == s || "all" == s {
-            return Ok(Arch {
-                kernel: None,
-                cpu: None,
-                boogered: false,
-            });
+        match s {
+            "all" => return Ok(Arch::All),
+            "native" => return Ok(Arch::Native),
+            _ => (),
+        }
+
+        let parts: Vec<&str> = s.split('-').collect();
+
+        if !parts.contains(&"any") {
+            return Ok(Arch::Tuple(
+                tuple_for_name(s).ok_or_else(|| anyhow!("unrecognised architecture: {:?}", s))?,
+            ));
         }
-        Ok(match s.rfind('-') {
+
+        // wildcards are written as (((abi-)libc-)os-)cpu, with any missing parts being `any`
+        ensure!(parts.len() <= 4, "too many parts in wildcard: {:?}", s);
+        let mut padded = vec!["any"; 4 - parts.len()];
+        padded.extend(parts);
+
+        Ok(Arch::Tuple(Tuple {
+            abi: wild_part(padded[0])?,
+            libc: wild_part(padded[1])?,
+            os: wild_part(padded[2])?,
+            cpu: wild_part(padded[3])?,
+        }))
+    }
+}
+
+fn wild_part<T: FromStr<Err = Error>>(part: &str) -> Result<Option<T>, Error> {
+    Ok(if "any" == part {
+        None
+    } else {
+        Some(part.parse()?)
+    })
+}
+
+impl Tuple {
+    fn from_full(tuple: &str) -> Result<Tuple, Error> {
+        let parts: Vec<&str> = tuple.split('-').collect();
+        ensure!(4 == parts.len(), "invalid tuple: {:?}", tuple);
+        Ok(Tuple {
+            abi: Some(parts[0].parse()?),
+            libc: Some(parts[1].parse()?),
+            os: Some(parts[2].parse()?),
+            cpu: Some(parts[3].parse()?),
+        })
+    }
+}
+
+/// Look up a Debian architecture name, e.g. `armhf`, in the `tupletable`.
+fn tuple_for_name(name: &str) -> Option<Tuple> {
+    for (tuple, arch) in TUPLE_TABLE {
+        match arch.find("<cpu>") {
             Some(pos) => {
-                let (kernel, cpu) = s.split_at(pos);
-                let kernel = if "any" == kernel {
-                    None
-                } else {
-                    Some(kernel.parse()?)
-                };
-
-                let cpu = &cpu[1..];
-
-                let cpu = if "any" == cpu {
-                    None
-                } else {
-                    Some(cpu.parse()?)
-                };
-
-                Arch {
-                    kernel,
-                    cpu,
-                    boogered: false,
+                let (prefix, suffix) = (&arch[..pos], &arch[pos + "<cpu>".len()..]);
+                if !name.starts_with(prefix) || !name.ends_with(suffix) {
+                    continue;
                 }
+                let cpu = &name[prefix.len()..name.len() - suffix.len()];
+                if cpu.parse::<Cpu>().is_err() {
+                    continue;
+                }
+                return Tuple::from_full(&tuple.replace("<cpu>", cpu)).ok();
             }
-            None => Arch {
-                kernel: None,
-                cpu: Some(s.parse()?),
-                boogered: false,
-            },
-        })
+            None if *arch == name => return Tuple::from_full(tuple).ok(),
+            None => continue,
+        }
     }
+    None
 }
 
-pub type Arches = HashSet<Arch>;
+/// The reverse of `tuple_for_name`.
+fn name_for_tuple(wanted: &Tuple) -> Option<String> {
+    let cpu = wanted.cpu?.to_string();
+    for (tuple, arch) in TUPLE_TABLE {
+        if let Ok(tuple) = Tuple::from_full(&tuple.replace("<cpu>", &cpu)) {
+            if tuple == *wanted {
+                return Some(arch.replace("<cpu>", &cpu));
+            }
+        }
+    }
+    None
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tuple = match self {
+            Arch::All => return write!(f, "all"),
+            Arch::Native => return write!(f, "native"),
+            Arch::Tuple(tuple) => tuple,
+        };
 
-macro_rules! strum {
-    ($doc:literal, $name:ident, $($variant:ident($str:expr),)*) => {
-        #[doc = $doc]
-        #[derive(Copy, Clone, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
-        pub enum $name {
-            $($variant,)*
+        if self.is_any() {
+            return write!(f, "any");
         }
 
-        impl FromStr for $name {
-            type Err = Error;
-            fn from_str(from: &str) -> Result<$name, Error> {
-                match from {
-                    $($str => Ok($name::$variant), )*
-                    other => bail!("no {}: {:?}", stringify!($name), other),
-                }
-            }
+        if let Some(name) = name_for_tuple(tuple) {
+            return write!(f, "{}", name);
+        }
+
+        fn part<T: fmt::Display>(part: Option<T>) -> String {
+            part.map(|p| p.to_string())
+                .unwrap_or_else(|| "any".to_string())
         }
+
+        if tuple.abi.is_some() {
+            write!(f, "{}-", part(tuple.abi))?;
+        }
+        if tuple.abi.is_some() || tuple.libc.is_some() {
+            write!(f, "{}-", part(tuple.libc))?;
+        }
+        write!(f, "{}-{}", part(tuple.os), part(tuple.cpu))
     }
 }
 
+pub type Arches = HashSet<Arch>;
+
 strum!(
-    "A host kernel-and-ecosystem, e.g. `linux`, `musl-linux`, `kfreebsd`, ...",
-    Kernel,
+    "An ABI variant, e.g. `eabihf`, `x32`, or just `base`, from dpkg's `abitable`.",
+    Abi,
+    Base("base"),
+    Abi64("abi64"),
+    Abin32("abin32"),
+    Eabi("eabi"),
+    Eabihf("eabihf"),
+    Ilp32("ilp32"),
+    Spe("spe"),
+    X32("x32"),
+);
+
+strum!(
+    "A libc (or other userland), e.g. `gnu`, `musl`, `bsd`, ...",
+    Libc,
+    Bsd("bsd"),
+    Gnu("gnu"),
+    Musl("musl"),
+    Sysv("sysv"),
+    Tos("tos"),
+    UcLibc("uclibc"),
+);
+
+strum!(
+    "A host kernel or operating system, e.g. `linux`, `kfreebsd`, `hurd`, ...",
+    Os,
     Aix("aix"),
     Darwin("darwin"),
     DragonflyBsd("dragonflybsd"),
@@ -120,30 +287,27 @@ strum!(
     KOpenSolaris("kopensolaris"),
     Linux("linux"),
     Mint("mint"),
-    MuslLinux("musl-linux"),
     NetBsd("netbsd"),
     OpenBsd("openbsd"),
     Solaris("solaris"),
-    UcLibcLinux("uclibc-linux"),
     UcLinux("uclinux"),
 );
 
 strum!(
-    "A host cpu architecture and configuration, e.g. `amd64`, `x32`, `arm64ilp32`, ...",
+    "A host cpu, e.g. `amd64`, `arm`, `mips64el`, from dpkg's `cputable`.",
     Cpu,
-    Native("native"),
     Alpha("alpha"),
     Amd64("amd64"),
+    Arc("arc"),
     Arm("arm"),
     Arm64("arm64"),
-    Arm64ilp32("arm64ilp32"),
     Armeb("armeb"),
-    Armel("armel"),
-    Armhf("armhf"),
     Avr32("avr32"),
     Hppa("hppa"),
     I386("i386"),
     Ia64("ia64"),
+    Loong64("loong64"),
+    // no longer in dpkg, but still in old Ubuntu archives
     Lpia("lpia"),
     M32r("m32r"),
     M68k("m68k"),
@@ -153,17 +317,12 @@ strum!(
     Mips64r6("mips64r6"),
     Mips64r6el("mips64r6el"),
     Mipsel("mipsel"),
-    Mipsn32("mipsn32"),
-    Mipsn32el("mipsn32el"),
-    Mipsn32r6("mipsn32r6"),
-    Mipsn32r6el("mipsn32r6el"),
     Mipsr6("mipsr6"),
     Mipsr6el("mipsr6el"),
     Nios2("nios2"),
     Or1k("or1k"),
     Powerpc("powerpc"),
     Powerpcel("powerpcel"),
-    Powerpcspe("powerpcspe"),
     Ppc64("ppc64"),
     Ppc64el("ppc64el"),
     Riscv64("riscv64"),
@@ -176,5 +335,81 @@ strum!(
     Sparc("sparc"),
     Sparc64("sparc64"),
     Tilegx("tilegx"),
-    X32("x32"),
 );
+
+#[cfg(test)]
+mod tests {
+    use super::Abi;
+    use super::Arch;
+    use super::Cpu;
+    use super::Libc;
+    use super::Os;
+    use super::Tuple;
+
+    fn arch(s: &str) -> Arch {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn tuples() {
+        assert_eq!(
+            Arch::Tuple(Tuple {
+                abi: Some(Abi::Eabihf),
+                libc: Some(Libc::Gnu),
+                os: Some(Os::Linux),
+                cpu: Some(Cpu::Arm),
+            }),
+            arch("armhf")
+        );
+        assert_eq!(
+            Arch::Tuple(Tuple {
+                abi: Some(Abi::Base),
+                libc: Some(Libc::Bsd),
+                os: Some(Os::FreeBsd),
+                cpu: Some(Cpu::Amd64),
+            }),
+            arch("freebsd-amd64")
+        );
+        assert_eq!(Arch::All, arch("all"));
+        assert_ne!(arch("all"), arch("any"));
+        assert!(arch("any").is_any());
+        assert!(arch("x32").is_concrete());
+        assert!("linux-foo".parse::<Arch>().is_err());
+        assert!("amd65".parse::<Arch>().is_err());
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(arch("any").matches(&arch("hurd-i386")));
+        assert!(arch("linux-any").matches(&arch("amd64")));
+        assert!(arch("linux-any").matches(&arch("musl-linux-arm64")));
+        assert!(!arch("linux-any").matches(&arch("kfreebsd-amd64")));
+        assert!(arch("any-amd64").matches(&arch("kfreebsd-amd64")));
+        assert!(arch("any-amd64").matches(&arch("x32")));
+        assert!(!arch("any-arm64").matches(&arch("amd64")));
+        assert!(arch("musl-linux-any").matches(&arch("musl-linux-armhf")));
+        assert!(!arch("musl-linux-any").matches(&arch("armhf")));
+        assert!(arch("amd64").matches(&arch("amd64")));
+        assert!(!arch("amd64").matches(&arch("x32")));
+        assert!(!arch("any").matches(&arch("all")));
+        assert!(arch("all").matches(&arch("all")));
+    }
+
+    #[test]
+    fn display() {
+        for name in &[
+            "all",
+            "any",
+            "native",
+            "amd64",
+            "armhf",
+            "kfreebsd-amd64",
+            "linux-any",
+            "any-arm64",
+            "musl-linux-any",
+            "eabihf-any-linux-any",
+        ] {
+            assert_eq!(*name, arch(name).to_string());
+        }
+    }
+}
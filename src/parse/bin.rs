@@ -1,3 +1,6 @@
+use std::fmt;
+use std::str::FromStr;
+
 use anyhow::bail;
 use anyhow::Error;
 use insideout::InsideOut;
@@ -6,6 +9,10 @@ use self::rfc822::RfcMapExt;
 use super::deps::parse_dep;
 use super::deps::Dependency;
 use super::pkg;
+use super::status::parse_conffiles;
+use super::status::Conffile;
+use super::status::Status;
+use super::version::Version;
 use crate::rfc822;
 
 /// Binary package specific fields.
@@ -16,12 +23,18 @@ pub struct Binary {
 
     pub essential: bool,
     pub build_essential: bool,
+    pub multi_arch: MultiArch,
 
     pub installed_size: u64,
 
     pub description: String,
     pub source: Option<String>,
-    pub status: Option<String>,
+    /// Only in the `dpkg` database.
+    pub status: Option<Status>,
+    /// Only in the `dpkg` database.
+    pub conffiles: Vec<Conffile>,
+    /// The last version which was configured, only in the `dpkg` database.
+    pub config_version: Option<Version>,
 
     pub depends: Vec<Dependency>,
     pub recommends: Vec<Dependency>,
@@ -36,6 +49,23 @@ pub struct Binary {
     pub provides: Vec<Dependency>,
 }
 
+strum!(
+    "How the package can satisfy, or be installed alongside, packages from other architectures.",
+    MultiArch,
+    No("no"),
+    Same("same"),
+    Foreign("foreign"),
+    Allowed("allowed"),
+);
+
+// `strum!` can't mark a `#[default]` variant.
+#[allow(clippy::derivable_impls)]
+impl Default for MultiArch {
+    fn default() -> Self {
+        MultiArch::No
+    }
+}
+
 pub(super) fn parse_bin(it: &mut rfc822::Map) -> Result<Binary, Error> {
     let file = if it.contains_key("Filename") {
         Some(super::pkg::File {
@@ -44,7 +74,7 @@ pub(super) fn parse_bin(it: &mut rfc822::Map) -> Result<Binary, Error> {
             md5: it.remove_value("MD5sum").one_line_owned()?,
             sha1: it.remove_value("SHA1").one_line_req()?.to_string(),
             sha256: it.remove_value("SHA256").one_line_req()?.to_string(),
-            sha512: String::new(),
+            sha512: it.remove_value("SHA512").one_line_owned()?,
         })
     } else {
         None
@@ -72,14 +102,32 @@ pub(super) fn parse_bin(it: &mut rfc822::Map) -> Result<Binary, Error> {
         .inside_out()?
         .unwrap_or(false);
 
+    let multi_arch = it
+        .remove_value("Multi-Arch")
+        .one_line()?
+        .map(|line| line.parse())
+        .inside_out()?
+        .unwrap_or_default();
+
     Ok(Binary {
         file,
         essential,
         build_essential,
+        multi_arch,
         installed_size,
         description: it.remove_value("Description").joined_lines_req()?,
         source: it.remove_value("Source").one_line_owned()?,
-        status: it.remove_value("Status").one_line_owned()?,
+        status: it
+            .remove_value("Status")
+            .one_line()?
+            .map(|status| status.parse())
+            .inside_out()?,
+        conffiles: parse_conffiles(&it.remove("Conffiles").unwrap_or_else(Vec::new))?,
+        config_version: it
+            .remove_value("Config-Version")
+            .one_line()?
+            .map(|version| version.parse())
+            .inside_out()?,
         depends: parse_dep(&it.remove("Depends").unwrap_or_else(Vec::new))?,
         recommends: parse_dep(&it.remove("Recommends").unwrap_or_else(Vec::new))?,
         suggests: parse_dep(&it.remove("Suggests").unwrap_or_else(Vec::new))?,
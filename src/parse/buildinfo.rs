@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use chrono::DateTime;
+use chrono::Utc;
+use gpgrv::Keyring;
+use insideout::InsideOut;
+
+use super::arch::Arch;
+use super::deps;
+use super::deps::Dependency;
+use super::upload::parse_upload;
+use super::upload::read_signed;
+use super::upload::Signature;
+use super::upload::Upload;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// A `.buildinfo` file, which records the environment a package was built in,
+/// so the build can be reproduced.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub upload: Upload,
+
+    /// The vendor of the build system, e.g. `debian`.
+    pub build_origin: Option<String>,
+    pub build_arch: Arch,
+    pub build_date: Option<DateTime<Utc>>,
+    /// Only recorded if it's not a temporary directory, as it may affect the build.
+    pub build_path: Option<String>,
+    /// Allowed environment variables, in order, with their (unquoted) values.
+    pub environment: Vec<(String, String)>,
+    /// Every package installed at build time, usually each pinned to an exact version.
+    pub installed_build_depends: Vec<Dependency>,
+
+    pub signature: Signature,
+
+    pub unparsed: HashMap<String, Vec<String>>,
+}
+
+impl BuildInfo {
+    /// Read a `.buildinfo`, requiring a good signature from the `keyring`, if there is one.
+    pub fn read<R: BufRead>(from: R, keyring: Option<&Keyring>) -> Result<BuildInfo, Error> {
+        let (body, signature) = read_signed(from, keyring)?;
+        let mut map = rfc822::fields_in_block(body.trim()).collect_to_map()?;
+        let mut info = parse_buildinfo(&mut map)?;
+        info.signature = signature;
+        Ok(info)
+    }
+}
+
+fn parse_buildinfo(map: &mut rfc822::Map) -> Result<BuildInfo, Error> {
+    Ok(BuildInfo {
+        upload: parse_upload(map)?,
+        build_origin: map.remove_value("Build-Origin").one_line_owned()?,
+        build_arch: map
+            .remove_value("Build-Architecture")
+            .one_line_req()?
+            .parse()?,
+        build_date: map
+            .remove_value("Build-Date")
+            .one_line()?
+            .map(rfc822::parse_date)
+            .inside_out()?,
+        build_path: map.remove_value("Build-Path").one_line_owned()?,
+        environment: map
+            .remove("Environment")
+            .unwrap_or_default()
+            .into_iter()
+            .map(parse_environment)
+            .collect::<Result<_, Error>>()?,
+        installed_build_depends: deps::read(
+            &map.remove_value("Installed-Build-Depends")
+                .joined_lines()
+                .unwrap_or_default(),
+        )
+        .with_context(|| anyhow!("reading Installed-Build-Depends"))?,
+        signature: Signature::Unsigned,
+        unparsed: rfc822::to_owned_map(map),
+    })
+}
+
+/// `NAME="value"`, where the value has `"` and `\` escaped with `\`.
+fn parse_environment(line: &str) -> Result<(String, String), Error> {
+    let (name, value) = line
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected NAME=\"value\", not {:?}", line))?;
+    ensure!(
+        value.len() >= 2 && value.starts_with('"') && value.ends_with('"'),
+        "unquoted value: {:?}",
+        line
+    );
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(
+                chars
+                    .next()
+                    .ok_or_else(|| anyhow!("trailing escape: {:?}", line))?,
+            ),
+            c => unescaped.push(c),
+        }
+    }
+
+    Ok((name.to_string(), unescaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_environment;
+    use super::BuildInfo;
+    use crate::parse::ConstraintOperator;
+    use crate::parse::Signature;
+
+    const BUILDINFO: &str = "Format: 1.0
+Source: hello (2.10-3)
+Binary: hello
+Architecture: amd64
+Version: 2.10-3+b1
+Checksums-Sha256:
+ 1e4d6fe1e0a6ae0fb12a5bb4a6b2e0e4e1a8d6f5f77a1b0e9e2c2f6d3a5b4c3d2 52412 hello_2.10-3+b1_amd64.deb
+Build-Origin: debian
+Build-Architecture: amd64
+Build-Date: Sat, 18 Feb 2023 12:00:00 +0000
+Build-Path: /build/reproducible-path/hello-2.10
+Installed-Build-Depends:
+ autoconf (= 2.71-3),
+ debhelper (= 13.11.4),
+ libc6 (= 2.36-9),
+ libc6-dev:amd64 (= 2.36-9)
+Environment:
+ DEB_BUILD_OPTIONS=\"parallel=4\"
+ LANG=\"C.UTF-8\"
+ QUOTED=\"say \\\"hi\\\"\"
+";
+
+    #[test]
+    fn buildinfo() {
+        let info = BuildInfo::read(BUILDINFO.as_bytes(), None).unwrap();
+        assert_eq!(Signature::Unsigned, info.signature);
+        assert_eq!("hello", info.upload.source);
+        assert_eq!("2.10-3", info.upload.source_version.unwrap().to_string());
+        assert_eq!("2.10-3+b1", info.upload.version.to_string());
+        assert_eq!(Some("debian".to_string()), info.build_origin);
+        assert_eq!("amd64", info.build_arch.to_string());
+        assert!(info.build_date.is_some());
+        assert_eq!(
+            Some("/build/reproducible-path/hello-2.10".to_string()),
+            info.build_path
+        );
+        assert_eq!(
+            ("DEB_BUILD_OPTIONS".to_string(), "parallel=4".to_string()),
+            info.environment[0]
+        );
+        assert_eq!("say \"hi\"", info.environment[2].1);
+
+        assert_eq!(4, info.installed_build_depends.len());
+        let libc = &info.installed_build_depends[3].alternate[0];
+        assert_eq!("libc6-dev", libc.package);
+        assert_eq!("amd64", libc.arch.as_ref().unwrap().to_string());
+        assert_eq!(ConstraintOperator::Eq, libc.version_constraints[0].operator);
+
+        assert!(info.unparsed.contains_key("Checksums-Sha256"));
+    }
+
+    #[test]
+    fn environment() {
+        assert!(parse_environment("LANG=C").is_err());
+        assert!(parse_environment("LANG").is_err());
+        assert!(parse_environment("LANG=\"C\\\"").is_err());
+        assert_eq!(
+            ("EMPTY".to_string(), String::new()),
+            parse_environment("EMPTY=\"\"").unwrap()
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::BufRead;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use chrono::DateTime;
+use chrono::Utc;
+use regex::Regex;
+
+use super::ident;
+use super::ident::Identity;
+use super::version::Version;
+use crate::rfc822;
+
+/// A `debian/changelog`, newest entry first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Changelog {
+    pub entries: Vec<ChangelogEntry>,
+}
+
+/// One upload, from `hello (2.10-3) unstable; urgency=medium` to the ` -- ` trailer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    pub package: String,
+    pub version: Version,
+    pub distributions: Vec<String>,
+    pub urgency: String,
+    /// The lines between the header and the trailer, without their usual two space indent,
+    /// e.g. `* Fix the frobnicator. Closes: #123`, without any leading or trailing blank lines.
+    pub changes: Vec<String>,
+    pub maintainer: Identity,
+    pub date: DateTime<Utc>,
+}
+
+impl Changelog {
+    pub fn parse<R: BufRead>(from: R) -> Result<Changelog, Error> {
+        let header_re =
+            Regex::new(r"^(\w[-+0-9a-z.]*) \(([^() \t]+)\)((?:\s+[-+0-9a-zA-Z.]+)+);(.*)$")
+                .expect("static regex");
+        let mut entries = Vec::new();
+        let mut current: Option<(ChangelogEntryHeader, Vec<String>)> = None;
+
+        for (no, line) in from.lines().enumerate() {
+            let line = line.with_context(|| anyhow!("reading around line {}", no))?;
+
+            let (header, changes) = match &mut current {
+                Some(current) => current,
+                None => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    // old, free-form entries, or emacs settings, which dpkg also ignores
+                    let lower = line.to_ascii_lowercase();
+                    if lower.starts_with("local variables:") || lower.starts_with("old changelog:")
+                    {
+                        break;
+                    }
+
+                    current = Some((
+                        parse_header(&header_re, &line)
+                            .with_context(|| anyhow!("parsing header at line {}", no + 1))?,
+                        Vec::new(),
+                    ));
+                    continue;
+                }
+            };
+
+            if let Some(trailer) = line.strip_prefix(" -- ") {
+                let (maintainer, date) = parse_trailer(trailer)
+                    .with_context(|| anyhow!("parsing trailer at line {}", no + 1))?;
+
+                while changes.last().is_some_and(|l| l.is_empty()) {
+                    changes.pop();
+                }
+                let start = changes.iter().take_while(|l| l.is_empty()).count();
+
+                entries.push(ChangelogEntry {
+                    package: header.package.to_string(),
+                    version: header.version.clone(),
+                    distributions: header.distributions.clone(),
+                    urgency: header.urgency.to_string(),
+                    changes: changes.split_off(start),
+                    maintainer,
+                    date,
+                });
+                current = None;
+                continue;
+            }
+
+            let line = line.trim_end();
+            changes.push(line.strip_prefix("  ").unwrap_or(line).to_string());
+        }
+
+        if let Some((header, _)) = current {
+            bail!("no trailer for {} {}", header.package, header.version);
+        }
+
+        ensure!(!entries.is_empty(), "no entries in changelog");
+
+        Ok(Changelog { entries })
+    }
+}
+
+struct ChangelogEntryHeader {
+    package: String,
+    version: Version,
+    distributions: Vec<String>,
+    urgency: String,
+}
+
+/// `hello (2.10-3) unstable; urgency=medium`
+fn parse_header(header_re: &Regex, line: &str) -> Result<ChangelogEntryHeader, Error> {
+    let captures = header_re
+        .captures(line)
+        .ok_or_else(|| anyhow!("invalid header: {:?}", line))?;
+
+    let mut urgency = "low".to_string();
+    for option in captures[4].split(',') {
+        let option = option.trim();
+        if option.is_empty() {
+            continue;
+        }
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected key=value, not {:?}", option))?;
+        if key.trim().eq_ignore_ascii_case("urgency") {
+            urgency = value
+                .split_whitespace()
+                .next()
+                .ok_or_else(|| anyhow!("empty urgency"))?
+                .to_ascii_lowercase();
+        }
+    }
+
+    Ok(ChangelogEntryHeader {
+        package: captures[1].to_string(),
+        version: captures[2].parse()?,
+        distributions: captures[3]
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect(),
+        urgency,
+    })
+}
+
+/// `Santiago Vila <sanvila@debian.org>  Sat, 18 Feb 2023 12:00:00 +0100`
+fn parse_trailer(trailer: &str) -> Result<(Identity, DateTime<Utc>), Error> {
+    let end = trailer
+        .find('>')
+        .ok_or_else(|| anyhow!("no maintainer in trailer: {:?}", trailer))?;
+    let (maintainer, date) = trailer.split_at(end + 1);
+
+    let mut maintainer = ident::read(maintainer)?;
+    ensure!(
+        1 == maintainer.len(),
+        "expected one maintainer, not {:?}",
+        maintainer
+    );
+
+    Ok((maintainer.remove(0), rfc822::parse_date(date.trim())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Changelog;
+    use crate::parse::Identity;
+
+    const CHANGELOG: &str = "hello (2.10-3) unstable; urgency=medium
+
+  * Add some autopkgtests. Closes: #871622.
+  * Add Vcs-Git and Vcs-Browser fields to debian/control:
+    - for salsa. Closes: #893083.
+
+ -- Santiago Vila <sanvila@debian.org>  Sat, 18 Feb 2023 12:00:00 +0100
+
+hello (2.10-2) unstable experimental; urgency=LOW (thanks), binary-only=yes
+  * Bump standards version.
+ -- Santiago Vila <sanvila@debian.org>  Mon, 30 Oct 2017 20:20:00 +0100
+
+Local variables:
+mode: debian-changelog
+End:
+";
+
+    #[test]
+    fn changelog() {
+        let changelog = Changelog::parse(CHANGELOG.as_bytes()).unwrap();
+        assert_eq!(2, changelog.entries.len());
+
+        let entry = &changelog.entries[0];
+        assert_eq!("hello", entry.package);
+        assert_eq!("2.10-3", entry.version.to_string());
+        assert_eq!(vec!["unstable"], entry.distributions);
+        assert_eq!("medium", entry.urgency);
+        assert_eq!(
+            vec![
+                "* Add some autopkgtests. Closes: #871622.",
+                "* Add Vcs-Git and Vcs-Browser fields to debian/control:",
+                "  - for salsa. Closes: #893083.",
+            ],
+            entry.changes
+        );
+        assert_eq!(
+            Identity::Parsed {
+                name: "Santiago Vila".to_string(),
+                email: "sanvila@debian.org".to_string(),
+            },
+            entry.maintainer
+        );
+        assert_eq!("2023-02-18 11:00:00 UTC", entry.date.to_string());
+
+        let entry = &changelog.entries[1];
+        assert_eq!(vec!["unstable", "experimental"], entry.distributions);
+        assert_eq!("low", entry.urgency);
+        assert_eq!(vec!["* Bump standards version."], entry.changes);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Changelog::parse(&b""[..]).is_err());
+        assert!(Changelog::parse(&b"hello 2.10-3 unstable; urgency=low\n"[..]).is_err());
+        assert!(Changelog::parse(&b"hello (2.10-3) unstable; urgency=low\n  * foo\n"[..]).is_err());
+        assert!(Changelog::parse(
+            &b"hello (2.10-3) unstable; urgency=low\n\n -- nobody  Sat, 18 Feb 2023 12:00:00 +0100\n"
+                [..]
+        )
+        .is_err());
+    }
+}
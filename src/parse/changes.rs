@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use chrono::DateTime;
+use chrono::Utc;
+use gpgrv::Keyring;
+use insideout::InsideOut;
+
+use super::ident;
+use super::ident::Identity;
+use super::pkg;
+use super::pkg::Priority;
+use super::upload::parse_upload;
+use super::upload::read_signed;
+use super::upload::Signature;
+use super::upload::Upload;
+use crate::checksum::parse_md5;
+use crate::checksum::parse_sha256;
+use crate::checksum::MD5;
+use crate::checksum::SHA256;
+use crate::download::verify_listed;
+use crate::release::take_checksums;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// A `.changes` file, which describes an upload to an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Changes {
+    pub upload: Upload,
+    pub date: DateTime<Utc>,
+    pub distribution: Vec<String>,
+    pub urgency: String,
+    pub maintainer: Vec<Identity>,
+    pub changed_by: Vec<Identity>,
+    /// Bug numbers.
+    pub closes: Vec<u64>,
+    /// The changelog entries, one per line, without indentation. Blank lines are empty.
+    pub changes: Vec<String>,
+    pub files: Vec<ChangesFile>,
+
+    pub signature: Signature,
+
+    pub unparsed: HashMap<String, Vec<String>>,
+}
+
+/// A file in an upload, from the `Files` of a `.changes`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangesFile {
+    pub name: String,
+    pub size: u64,
+    /// e.g. `devel`, `non-free/games`, or `byhand`.
+    pub section: String,
+    /// `Unknown` for files which aren't packages, which have a priority of `-`.
+    pub priority: Priority,
+    pub md5: MD5,
+    pub sha256: Option<SHA256>,
+}
+
+impl Changes {
+    /// Read a `.changes`, requiring a good signature from the `keyring`, if there is one.
+    pub fn read<R: BufRead>(from: R, keyring: Option<&Keyring>) -> Result<Changes, Error> {
+        let (body, signature) = read_signed(from, keyring)?;
+        let mut map = rfc822::fields_in_block(body.trim()).collect_to_map()?;
+        let mut changes = parse_changes(&mut map)?;
+        changes.signature = signature;
+        Ok(changes)
+    }
+
+    /// Check every file in the upload is present in `dir`, with the right size and hashes.
+    pub fn verify_files<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        for file in &self.files {
+            verify_listed(
+                dir,
+                &file.name,
+                file.size,
+                Some(file.md5),
+                file.sha256,
+                None,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_changes(map: &mut rfc822::Map) -> Result<Changes, Error> {
+    Ok(Changes {
+        upload: parse_upload(map)?,
+        date: rfc822::parse_date(map.remove_value("Date").one_line_req()?)?,
+        distribution: map.remove_value("Distribution").split_whitespace()?,
+        urgency: map
+            .remove_value("Urgency")
+            .one_line()?
+            .unwrap_or("low")
+            .to_string(),
+        maintainer: ident::read(map.remove_value("Maintainer").one_line_req()?)?,
+        changed_by: map
+            .remove_value("Changed-By")
+            .one_line()?
+            .map(ident::read)
+            .inside_out()?
+            .unwrap_or_else(Vec::new),
+        closes: map
+            .remove_value("Closes")
+            .one_line()?
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|bug| bug.parse())
+                    .collect::<Result<Vec<u64>, _>>()
+            })
+            .inside_out()
+            .with_context(|| anyhow!("reading Closes"))?
+            .unwrap_or_else(Vec::new),
+        changes: map
+            .remove_value("Changes")
+            .required()?
+            .iter()
+            .map(|line| if "." == *line { "" } else { line }.to_string())
+            .collect(),
+        files: take_files(map)?,
+        signature: Signature::Unsigned,
+        unparsed: rfc822::to_owned_map(map),
+    })
+}
+
+fn take_files(map: &mut rfc822::Map) -> Result<Vec<ChangesFile>, Error> {
+    let mut file_and_size_to_sha256 =
+        take_checksums(map, "Checksums-Sha256")?.unwrap_or_else(HashMap::new);
+
+    let lines = map.remove_value("Files").required()?.to_vec();
+    let mut files = Vec::with_capacity(lines.len());
+    for line in lines {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        ensure!(5 == parts.len(), "invalid Files line: {:?}", line);
+        let name = parts[4];
+        let size = parts[1].parse()?;
+        files.push(ChangesFile {
+            name: name.to_string(),
+            size,
+            section: parts[2].to_string(),
+            priority: match parts[3] {
+                "-" => Priority::Unknown,
+                other => pkg::parse_priority(other)?,
+            },
+            md5: parse_md5(parts[0])?,
+            sha256: file_and_size_to_sha256
+                .remove(&(name, size))
+                .map(parse_sha256)
+                .inside_out()?,
+        });
+    }
+
+    ensure!(
+        file_and_size_to_sha256.is_empty(),
+        "sha256sum for a file which didn't exist: {:?}",
+        file_and_size_to_sha256
+    );
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use gpgrv::Keyring;
+
+    use super::Changes;
+    use crate::parse::Identity;
+    use crate::parse::Priority;
+    use crate::parse::Signature;
+
+    const CHANGES: &[u8] = include_bytes!("../../tests/dsc/hello_2.10-3_source.changes");
+
+    #[test]
+    fn changes() {
+        let mut keyring = Keyring::new();
+        keyring
+            .append_keys_from_armoured(&include_bytes!("../../tests/dsc/key.asc")[..])
+            .unwrap();
+
+        let changes = Changes::read(CHANGES, Some(&keyring)).unwrap();
+        assert_eq!(Signature::Verified, changes.signature);
+        assert_eq!("hello", changes.upload.source);
+        assert_eq!(None, changes.upload.source_version);
+        assert_eq!(vec!["hello"], changes.upload.binaries);
+        assert_eq!(vec!["source"], changes.upload.arches);
+        assert_eq!(vec!["unstable"], changes.distribution);
+        assert_eq!("medium", changes.urgency);
+        assert_eq!(
+            vec![Identity::Parsed {
+                name: "Santiago Vila".to_string(),
+                email: "sanvila@debian.org".to_string(),
+            }],
+            changes.changed_by
+        );
+        assert_eq!(vec![871622, 893083], changes.closes);
+        assert_eq!("", changes.changes[1]);
+        assert_eq!(
+            "* Add some autopkgtests. Closes: #871622.",
+            changes.changes[2]
+        );
+        assert_eq!(3, changes.files.len());
+        assert_eq!("hello_2.10-3.dsc", changes.files[0].name);
+        assert_eq!("devel", changes.files[0].section);
+        assert_eq!(Priority::Optional, changes.files[0].priority);
+        assert!(changes.files.iter().all(|f| f.sha256.is_some()));
+        assert!(changes.unparsed.contains_key("Description"));
+
+        changes
+            .verify_files(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/dsc"))
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        assert!(changes.verify_files(dir.path()).is_err());
+
+        let tampered = String::from_utf8(CHANGES.to_vec())
+            .unwrap()
+            .replace("urgency=medium", "urgency=high");
+        assert!(Changes::read(tampered.as_bytes(), Some(&keyring)).is_err());
+        assert!(Changes::read(tampered.as_bytes(), None).is_ok());
+    }
+}
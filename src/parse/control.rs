@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use insideout::InsideOut;
+
+use super::deps;
+use super::deps::Dependency;
+use super::deps::RestrictionList;
+use super::ident;
+use super::ident::Identity;
+use super::pkg;
+use super::pkg::Priority;
+use super::src::SourceBinary;
+use super::vcs;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// A `debian/control` file, from a source tree: the source paragraph, then the binaries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Control {
+    pub source: ControlSource,
+    pub binaries: Vec<ControlBinary>,
+}
+
+/// The first paragraph of a `debian/control`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlSource {
+    pub name: String,
+    pub section: Option<String>,
+    pub priority: Option<Priority>,
+    pub maintainer: Vec<Identity>,
+    pub uploaders: Vec<Identity>,
+    pub standards_version: Option<String>,
+    pub homepage: Option<String>,
+    pub vcs: Vec<vcs::Vcs>,
+
+    pub build_dep: Vec<Relation>,
+    pub build_dep_arch: Vec<Relation>,
+    pub build_dep_indep: Vec<Relation>,
+    pub build_conflict: Vec<Relation>,
+    pub build_conflict_arch: Vec<Relation>,
+    pub build_conflict_indep: Vec<Relation>,
+
+    pub unparsed: HashMap<String, Vec<String>>,
+}
+
+/// A binary paragraph of a `debian/control`. There's no `Version`; it comes from the changelog.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlBinary {
+    pub name: String,
+    /// As written, e.g. `any`, `all`, or `linux-any`.
+    pub arches: Vec<String>,
+    /// `None` if it's inherited from the source.
+    pub section: Option<String>,
+    /// `None` if it's inherited from the source.
+    pub priority: Option<Priority>,
+    /// `deb`, or `udeb`.
+    pub package_type: String,
+    pub essential: bool,
+    /// Which build profiles this binary is built in, e.g. `<!nocheck>`. Empty for all of them.
+    pub build_profiles: Vec<RestrictionList>,
+
+    pub depends: Vec<Relation>,
+    pub pre_depends: Vec<Relation>,
+    pub recommends: Vec<Relation>,
+    pub suggests: Vec<Relation>,
+    pub enhances: Vec<Relation>,
+    pub breaks: Vec<Relation>,
+    pub conflicts: Vec<Relation>,
+    pub provides: Vec<Relation>,
+    pub replaces: Vec<Relation>,
+
+    pub unparsed: HashMap<String, Vec<String>>,
+}
+
+/// An entry in a relationship field of a `debian/control`, which may not be
+/// a real dependency until the substitution variables are expanded at build time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Relation {
+    Dependency(Dependency),
+    /// A whole entry which is a variable, e.g. `shlibs:Depends` from `${shlibs:Depends}`.
+    /// It may expand to any number of dependencies, including none.
+    Substvar(String),
+    /// A dependency which uses variables, e.g. `foo (= ${binary:Version})`, as written.
+    Templated(String),
+}
+
+impl Control {
+    pub fn parse<R: BufRead>(from: R) -> Result<Control, Error> {
+        let stanzas = rfc822::commented_blocks(from)?;
+
+        let mut stanzas = stanzas.into_iter().map(|(start, stanza)| {
+            rfc822::fields_in_block(&stanza)
+                .collect_to_map()
+                .and_then(|mut map| Ok((start, parse_paragraph(&mut map)?)))
+                .with_context(|| anyhow!("parsing stanza at line {}", start + 1))
+        });
+
+        let source = match stanzas.next().inside_out()? {
+            Some((_, Paragraph::Source(source))) => source,
+            Some((start, Paragraph::Binary(_))) => {
+                bail!("expected a Source paragraph first, at line {}", start + 1)
+            }
+            None => bail!("empty control file"),
+        };
+
+        let mut binaries = Vec::new();
+        for stanza in stanzas {
+            match stanza? {
+                (_, Paragraph::Binary(binary)) => binaries.push(binary),
+                (start, Paragraph::Source(_)) => {
+                    bail!("a second Source paragraph, at line {}", start + 1)
+                }
+            }
+        }
+
+        Ok(Control { source, binaries })
+    }
+
+    /// The `Binary` field that would appear in the `.dsc`, and the `Sources` index.
+    pub fn binary(&self) -> Vec<String> {
+        self.binaries.iter().map(|b| b.name.to_string()).collect()
+    }
+
+    /// The `Package-List` that would appear in the `.dsc`, and the `Sources` index.
+    pub fn package_list(&self) -> Vec<SourceBinary> {
+        self.binaries
+            .iter()
+            .map(|binary| {
+                let mut extras = vec![format!("arch={}", binary.arches.join(","))];
+                if !binary.build_profiles.is_empty() {
+                    extras.push(format!(
+                        "profile={}",
+                        binary
+                            .build_profiles
+                            .iter()
+                            .map(|list| {
+                                list.terms
+                                    .iter()
+                                    .map(|(enabled, name)| {
+                                        format!("{}{}", if *enabled { "" } else { "!" }, name)
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join(",")
+                            })
+                            .collect::<Vec<_>>()
+                            .join("+")
+                    ));
+                }
+                if binary.essential {
+                    extras.push("essential=yes".to_string());
+                }
+
+                SourceBinary {
+                    name: binary.name.to_string(),
+                    style: binary.package_type.to_string(),
+                    section: binary
+                        .section
+                        .as_ref()
+                        .or(self.source.section.as_ref())
+                        .map(|s| s.as_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                    priority: binary
+                        .priority
+                        .or(self.source.priority)
+                        .unwrap_or(Priority::Unknown),
+                    extras,
+                }
+            })
+            .collect()
+    }
+}
+
+enum Paragraph {
+    Source(ControlSource),
+    Binary(ControlBinary),
+}
+
+fn parse_paragraph(map: &mut rfc822::Map) -> Result<Paragraph, Error> {
+    Ok(if map.contains_key("Source") {
+        ensure!(
+            !map.contains_key("Package"),
+            "both Source and Package in a paragraph"
+        );
+        Paragraph::Source(parse_source(map)?)
+    } else {
+        Paragraph::Binary(parse_binary(map)?)
+    })
+}
+
+fn parse_source(map: &mut rfc822::Map) -> Result<ControlSource, Error> {
+    Ok(ControlSource {
+        name: map.remove_value("Source").one_line_req()?.to_string(),
+        section: map.remove_value("Section").one_line_owned()?,
+        priority: take_priority(map)?,
+        maintainer: ident::read(map.remove_value("Maintainer").one_line_req()?)?,
+        uploaders: map
+            .remove_value("Uploaders")
+            .joined_lines()
+            .map(|line| ident::read(&line))
+            .inside_out()?
+            .unwrap_or_default(),
+        standards_version: map.remove_value("Standards-Version").one_line_owned()?,
+        homepage: map.remove_value("Homepage").one_line_owned()?,
+        vcs: vcs::extract(map)?,
+        build_dep: take_relations(map, "Build-Depends")?,
+        build_dep_arch: take_relations(map, "Build-Depends-Arch")?,
+        build_dep_indep: take_relations(map, "Build-Depends-Indep")?,
+        build_conflict: take_relations(map, "Build-Conflicts")?,
+        build_conflict_arch: take_relations(map, "Build-Conflicts-Arch")?,
+        build_conflict_indep: take_relations(map, "Build-Conflicts-Indep")?,
+        unparsed: rfc822::to_owned_map(map),
+    })
+}
+
+fn parse_binary(map: &mut rfc822::Map) -> Result<ControlBinary, Error> {
+    Ok(ControlBinary {
+        name: map.remove_value("Package").one_line_req()?.to_string(),
+        arches: map.remove_value("Architecture").split_whitespace()?,
+        section: map.remove_value("Section").one_line_owned()?,
+        priority: take_priority(map)?,
+        package_type: map
+            .remove_value("Package-Type")
+            .one_line()?
+            .unwrap_or("deb")
+            .to_string(),
+        essential: match map.remove_value("Essential").one_line()? {
+            Some("yes") => true,
+            Some("no") | None => false,
+            Some(other) => bail!("invalid Essential: {:?}", other),
+        },
+        build_profiles: map
+            .remove_value("Build-Profiles")
+            .joined_lines()
+            .map(|line| parse_profiles(&line))
+            .inside_out()?
+            .unwrap_or_default(),
+        depends: take_relations(map, "Depends")?,
+        pre_depends: take_relations(map, "Pre-Depends")?,
+        recommends: take_relations(map, "Recommends")?,
+        suggests: take_relations(map, "Suggests")?,
+        enhances: take_relations(map, "Enhances")?,
+        breaks: take_relations(map, "Breaks")?,
+        conflicts: take_relations(map, "Conflicts")?,
+        provides: take_relations(map, "Provides")?,
+        replaces: take_relations(map, "Replaces")?,
+        unparsed: rfc822::to_owned_map(map),
+    })
+}
+
+fn take_priority(map: &mut rfc822::Map) -> Result<Option<Priority>, Error> {
+    map.remove_value("Priority")
+        .one_line()?
+        .map(pkg::parse_priority)
+        .inside_out()
+}
+
+fn take_relations(map: &mut rfc822::Map, key: &str) -> Result<Vec<Relation>, Error> {
+    map.remove_value(key)
+        .joined_lines()
+        .map(|line| parse_relations(&line))
+        .inside_out()
+        .with_context(|| anyhow!("reading {}", key))
+        .map(Option::unwrap_or_default)
+}
+
+fn parse_relations(line: &str) -> Result<Vec<Relation>, Error> {
+    line.split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            if let Some(name) = entry
+                .strip_prefix("${")
+                .and_then(|rest| rest.strip_suffix('}'))
+                .filter(|name| !name.contains('$'))
+            {
+                return Ok(Relation::Substvar(name.to_string()));
+            }
+
+            if entry.contains("${") {
+                return Ok(Relation::Templated(entry.to_string()));
+            }
+
+            let mut deps = deps::read(entry)?;
+            ensure!(1 == deps.len(), "expected one dependency: {:?}", entry);
+            Ok(Relation::Dependency(deps.remove(0)))
+        })
+        .collect()
+}
+
+/// `<!nocheck> <stage1 cross>`
+fn parse_profiles(line: &str) -> Result<Vec<RestrictionList>, Error> {
+    let mut lists = Vec::new();
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let inner = rest
+            .strip_prefix('<')
+            .and_then(|rest| rest.split_once('>'))
+            .ok_or_else(|| anyhow!("invalid Build-Profiles: {:?}", line))?;
+        lists.push(RestrictionList {
+            terms: inner
+                .0
+                .split_whitespace()
+                .map(|term| match term.strip_prefix('!') {
+                    Some(term) => (false, term.to_string()),
+                    None => (true, term.to_string()),
+                })
+                .collect(),
+        });
+        rest = inner.1.trim_start();
+    }
+    Ok(lists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Control;
+    use super::Relation;
+    use crate::parse::Priority;
+
+    const CONTROL: &str = "Source: hello
+Section: devel
+Priority: optional
+Maintainer: Santiago Vila <sanvila@debian.org>
+Standards-Version: 4.6.2
+Build-Depends: debhelper-compat (= 13), ${misc:Build}
+# a comment, which isn't part of the paragraph
+Vcs-Git: https://salsa.debian.org/sanvila/hello.git
+
+Package: hello
+Architecture: any
+Depends: ${shlibs:Depends}, ${misc:Depends},
+ hello-data (= ${source:Version}), libc6 (>= 2.34)
+Description: example package based on GNU hello
+
+Package: hello-data
+Architecture: all
+Multi-Arch: foreign
+Priority: standard
+Description: data for hello
+
+Package: hello-udeb
+Package-Type: udeb
+Section: debian-installer
+Architecture: linux-any kfreebsd-any
+Build-Profiles: <!noudeb> <stage1 !cross>
+Description: hello, for the installer
+";
+
+    #[test]
+    fn control() {
+        let control = Control::parse(CONTROL.as_bytes()).unwrap();
+        assert_eq!("hello", control.source.name);
+        assert_eq!(2, control.source.build_dep.len());
+        assert_eq!(
+            Relation::Substvar("misc:Build".to_string()),
+            control.source.build_dep[1]
+        );
+        assert_eq!(1, control.source.vcs.len());
+
+        let hello = &control.binaries[0];
+        assert_eq!(
+            vec![
+                Relation::Substvar("shlibs:Depends".to_string()),
+                Relation::Substvar("misc:Depends".to_string()),
+                Relation::Templated("hello-data (= ${source:Version})".to_string()),
+            ],
+            hello.depends[..3].to_vec()
+        );
+        match &hello.depends[3] {
+            Relation::Dependency(dep) => assert_eq!("libc6 (>= 2.34)", dep.to_string()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(control.binaries[1].unparsed.contains_key("Multi-Arch"));
+
+        assert_eq!(vec!["hello", "hello-data", "hello-udeb"], control.binary());
+
+        let list: Vec<String> = control
+            .package_list()
+            .iter()
+            .map(|b| {
+                format!(
+                    "{} {} {} {:?} {}",
+                    b.name,
+                    b.style,
+                    b.section,
+                    b.priority,
+                    b.extras.join(" ")
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "hello deb devel Optional arch=any",
+                "hello-data deb devel Standard arch=all",
+                "hello-udeb udeb debian-installer Optional \
+                 arch=linux-any,kfreebsd-any profile=!noudeb+stage1,!cross",
+            ],
+            list
+        );
+        assert_eq!(Priority::Optional, control.package_list()[0].priority);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Control::parse(&b""[..]).is_err());
+        assert!(Control::parse(&b"Package: foo\nArchitecture: any\n"[..]).is_err());
+        assert!(Control::parse(
+            &b"Source: foo\nMaintainer: A <a@example.com>\n\nSource: bar\n"[..]
+        )
+        .is_err());
+        assert!(Control::parse(
+            &b"Source: foo\nMaintainer: A <a@example.com>\nBuild-Depends: (>= 2)\n"[..]
+        )
+        .is_err());
+    }
+}
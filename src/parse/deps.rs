@@ -1,14 +1,16 @@
 use std::cmp;
 use std::collections::HashSet;
+use std::fmt;
 
 use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Error;
-use deb_version::compare_versions;
 use insideout::InsideOut;
 use nom::types::CompleteStr;
 
 use super::arch::Arch;
+use super::version::Version;
 use crate::rfc822;
 
 /// One-or-more alternate dependencies from a dependency list. e.g. `foo (>2.1) | bar [!i386 !amd64]`
@@ -25,13 +27,23 @@ pub struct SingleDependency {
     /// Note: It's possible Debian only supports a single version constraint.
     pub version_constraints: Vec<Constraint>,
     pub arch_filter: HashSet<(bool, Arch)>,
-    pub stage_filter: Vec<String>,
+    /// The build profile restriction formula: the dependency applies if any of the lists hold.
+    pub stage_filter: Vec<RestrictionList>,
+}
+
+/// One `<...>` group of build profile terms, e.g. `<!nocheck cross>`.
+///
+/// Each term is a profile name, and whether it must be enabled (`true`) or disabled.
+/// The list holds if all of its terms do.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct RestrictionList {
+    pub terms: Vec<(bool, String)>,
 }
 
 /// A constraint on a version, e.g. `>2.1`
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Constraint {
-    pub version: String,
+    pub version: Version,
     pub operator: ConstraintOperator,
 }
 
@@ -45,6 +57,68 @@ pub enum ConstraintOperator {
     Lt,
 }
 
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, alt) in self.alternate.iter().enumerate() {
+            if 0 != i {
+                write!(f, " | ")?;
+            }
+            write!(f, "{}", alt)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SingleDependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.package)?;
+        if let Some(arch) = &self.arch {
+            write!(f, ":{}", arch)?;
+        }
+        for constraint in &self.version_constraints {
+            write!(f, " ({})", constraint)?;
+        }
+        if !self.arch_filter.is_empty() {
+            let mut filter: Vec<String> = self
+                .arch_filter
+                .iter()
+                .map(|(positive, arch)| format!("{}{}", if *positive { "" } else { "!" }, arch))
+                .collect();
+            filter.sort();
+            write!(f, " [{}]", filter.join(" "))?;
+        }
+        for list in &self.stage_filter {
+            write!(f, " {}", list)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RestrictionList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<String> = self
+            .terms
+            .iter()
+            .map(|(positive, profile)| format!("{}{}", if *positive { "" } else { "!" }, profile))
+            .collect();
+        write!(f, "<{}>", terms.join(" "))
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ConstraintOperator::*;
+        let operator = match self.operator {
+            Ge => ">=",
+            Eq => "=",
+            Le => "<=",
+            Gt => ">>",
+            Lt => "<<",
+        };
+        write!(f, "{} {}", operator, self.version)
+    }
+}
+
 pub fn parse_dep(multi_str: &[&str]) -> Result<Vec<Dependency>, Error> {
     read(&rfc822::joined(multi_str))
 }
@@ -72,19 +146,96 @@ fn is_version_char(val: char) -> bool {
 }
 
 impl Constraint {
-    pub fn new(operator: ConstraintOperator, version: &str) -> Self {
-        Constraint {
-            operator,
-            version: version.to_string(),
+    pub fn new(operator: ConstraintOperator, version: Version) -> Self {
+        Constraint { operator, version }
+    }
+
+    pub fn satisfied_by(&self, version: &Version) -> bool {
+        self.operator.satisfied_by(version.cmp(&self.version))
+    }
+}
+
+impl SingleDependency {
+    /// Does this dependency apply when building for `host`, with these build profiles enabled?
+    ///
+    /// Dependencies with no arch or stage filters always apply.
+    pub fn applies_to<S: AsRef<str>>(&self, host: &Arch, profiles: &[S]) -> bool {
+        self.arch_applies_to(host) && self.stage_applies_to(profiles)
+    }
+
+    fn arch_applies_to(&self, host: &Arch) -> bool {
+        if self.arch_filter.is_empty() {
+            return true;
+        }
+
+        // Policy forbids mixing positive and negative filters, so either
+        // one of the positive ones must match, or none of the negative ones
+        let mut positive = false;
+        for (include, arch) in &self.arch_filter {
+            if arch.matches(host) {
+                return *include;
+            }
+            positive |= *include;
         }
+        !positive
+    }
+
+    fn stage_applies_to<S: AsRef<str>>(&self, profiles: &[S]) -> bool {
+        self.stage_filter.is_empty() || self.stage_filter.iter().any(|list| list.holds(profiles))
+    }
+}
+
+impl RestrictionList {
+    /// Do all of the terms hold, with these build profiles enabled?
+    pub fn holds<S: AsRef<str>>(&self, profiles: &[S]) -> bool {
+        self.terms
+            .iter()
+            .all(|(positive, profile)| *positive == profiles.iter().any(|p| p.as_ref() == profile))
     }
 
-    pub fn satisfied_by<S: AsRef<str>>(&self, version: S) -> bool {
-        self.operator
-            .satisfied_by(compare_versions(version.as_ref(), &self.version))
+    fn parse(list: &str) -> Result<RestrictionList, Error> {
+        let terms = list
+            .split_whitespace()
+            .map(|term| match term.strip_prefix('!') {
+                Some(profile) => (false, profile.to_string()),
+                None => (true, term.to_string()),
+            })
+            .collect::<Vec<_>>();
+        ensure!(
+            !terms.is_empty() && terms.iter().all(|(_, profile)| !profile.is_empty()),
+            "invalid restriction list: <{}>",
+            list
+        );
+        Ok(RestrictionList { terms })
     }
 }
 
+/// The dependencies which apply when building for `host` with these build profiles enabled.
+///
+/// Alternatives which are filtered out are dropped, and so are any dependencies
+/// which are left with no alternatives, as `dpkg-checkbuilddeps` does.
+pub fn reduce_dependencies<S: AsRef<str>>(
+    deps: &[Dependency],
+    host: &Arch,
+    profiles: &[S],
+) -> Vec<Dependency> {
+    deps.iter()
+        .filter_map(|dep| {
+            let alternate: Vec<SingleDependency> = dep
+                .alternate
+                .iter()
+                .filter(|alt| alt.applies_to(host, profiles))
+                .cloned()
+                .collect();
+            if alternate.is_empty() {
+                None
+            } else {
+                Some(Dependency { alternate })
+            }
+        })
+        .collect()
+}
+
 impl ConstraintOperator {
     fn satisfied_by(&self, ordering: cmp::Ordering) -> bool {
         use self::ConstraintOperator::*;
@@ -103,7 +254,7 @@ impl ConstraintOperator {
 named!(package_name<CompleteStr, CompleteStr>, take_while1_s!(is_package_name_char));
 named!(version<CompleteStr, CompleteStr>, take_while1_s!(is_version_char));
 
-named!(version_constraint<CompleteStr, Constraint>,
+named!(version_constraint<CompleteStr, (ConstraintOperator, CompleteStr)>,
 ws!(do_parse!(
     tag!("(") >>
     operator: alt!(
@@ -117,7 +268,7 @@ ws!(do_parse!(
     ) >>
     version: version >>
     tag!(")") >>
-    ( Constraint::new(operator, version.0) )
+    ( (operator, version) )
 )));
 
 named!(arch_part<CompleteStr, (bool, CompleteStr)>,
@@ -166,7 +317,7 @@ fn to_arch(s: CompleteStr) -> Result<Arch, Error> {
 fn build_single_dep(
     package: CompleteStr,
     arch: Option<CompleteStr>,
-    version_constraints: Vec<Constraint>,
+    version_constraints: Vec<(ConstraintOperator, CompleteStr)>,
     arch_filter: Option<Vec<(bool, CompleteStr)>>,
     stage_filter: Vec<CompleteStr>,
 ) -> Result<SingleDependency, Error> {
@@ -176,14 +327,22 @@ fn build_single_dep(
             .map(|s| to_arch(s))
             .inside_out()
             .with_context(|| anyhow!("explicit arch in dep {:?}", package))?,
-        version_constraints,
+        version_constraints: version_constraints
+            .into_iter()
+            .map(|(operator, version)| Ok(Constraint::new(operator, version.0.parse()?)))
+            .collect::<Result<_, Error>>()
+            .with_context(|| anyhow!("version constraint in dep {:?}", package))?,
         arch_filter: arch_filter
             .unwrap_or_else(Vec::new)
             .into_iter()
             .map(|(positive, arch)| to_arch(arch).map(|a| (positive, a)))
             .collect::<Result<HashSet<(bool, Arch)>, Error>>()
             .with_context(|| anyhow!("arch filter in dep {:?}", package))?,
-        stage_filter: stage_filter.into_iter().map(|x| x.0.to_string()).collect(),
+        stage_filter: stage_filter
+            .into_iter()
+            .map(|x| RestrictionList::parse(x.0))
+            .collect::<Result<_, Error>>()
+            .with_context(|| anyhow!("stage filter in dep {:?}", package))?,
         package,
     })
 }
@@ -225,10 +384,7 @@ fn check() {
     );
 
     assert_eq!(
-        (
-            CompleteStr(""),
-            Constraint::new(ConstraintOperator::Gt, "1")
-        ),
+        (CompleteStr(""), (ConstraintOperator::Gt, CompleteStr("1"))),
         version_constraint(CompleteStr("(>> 1)")).unwrap()
     );
 
@@ -246,7 +402,66 @@ fn check() {
 
 #[test]
 fn constraint_version() {
-    let cons = Constraint::new(ConstraintOperator::Gt, "1.0");
-    assert!(cons.satisfied_by("2.0"));
-    assert!(!cons.satisfied_by("1.0"));
+    let cons = Constraint::new(ConstraintOperator::Gt, "1.0".parse().unwrap());
+    assert!(cons.satisfied_by(&"2.0".parse().unwrap()));
+    assert!(!cons.satisfied_by(&"1.0".parse().unwrap()));
+}
+
+#[test]
+fn applies_to() {
+    let amd64: Arch = "amd64".parse().unwrap();
+    let first = |s: &str| read(s).unwrap().remove(0).alternate.remove(0);
+    let none: &[&str] = &[];
+
+    assert!(first("foo").applies_to(&amd64, none));
+    assert!(first("foo [amd64 i386]").applies_to(&amd64, none));
+    assert!(!first("foo [i386]").applies_to(&amd64, none));
+    assert!(!first("foo [!amd64]").applies_to(&amd64, none));
+    assert!(first("foo [!i386]").applies_to(&amd64, none));
+    assert!(first("foo [linux-any]").applies_to(&amd64, none));
+    assert!(!first("foo [linux-any]").applies_to(&"hurd-i386".parse().unwrap(), none));
+    assert!(first("foo [any-i386]").applies_to(&"hurd-i386".parse().unwrap(), none));
+
+    assert!(first("foo <!nocheck>").applies_to(&amd64, none));
+    assert!(!first("foo <!nocheck>").applies_to(&amd64, &["nocheck"]));
+    assert!(!first("foo <stage1 cross>").applies_to(&amd64, &["stage1"]));
+    assert!(first("foo <stage1 cross> <nocheck>").applies_to(&amd64, &["nocheck"]));
+}
+
+#[test]
+fn display() {
+    for dep in &[
+        "foo",
+        "foo:any (>= 1.2-3) | bar (<< 2)",
+        "foo [!i386 !linux-any] <!nocheck> <stage1 cross>",
+    ] {
+        assert_eq!(*dep, read(dep).unwrap()[0].to_string());
+    }
+}
+
+#[test]
+fn reduce() {
+    let deps = read("a [amd64] | b [i386], c [i386] <stage1>, d <!nocheck> <cross>, e").unwrap();
+    let amd64: Arch = "amd64".parse().unwrap();
+
+    let reduced: Vec<String> = reduce_dependencies(&deps, &amd64, &["nocheck"])
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+    assert_eq!(vec!["a [amd64]", "e"], reduced);
+
+    let reduced = reduce_dependencies(&deps, &amd64, &["cross", "nocheck"]);
+    assert_eq!(3, reduced.len());
+
+    assert_eq!(
+        vec![
+            RestrictionList {
+                terms: vec![(false, "nocheck".to_string()), (true, "cross".to_string())],
+            },
+            RestrictionList {
+                terms: vec![(true, "stage1".to_string())],
+            },
+        ],
+        read("d <!nocheck cross> <stage1>").unwrap()[0].alternate[0].stage_filter
+    );
 }
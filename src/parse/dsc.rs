@@ -0,0 +1,154 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use gpgrv::Keyring;
+
+use super::pkg;
+use super::pkg::Package;
+use super::pkg::PackageType;
+use super::src;
+use super::src::Source;
+use super::upload::read_signed;
+use super::upload::Signature;
+use crate::download::verify_listed;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// A `.dsc` file, which describes a source package outside of an archive, e.g. an upload.
+///
+/// The `Package` is the same as one from a `Sources` index, except that its `directory`
+/// is empty, as there isn't one (yet).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dsc {
+    pub package: Package,
+    pub signature: Signature,
+}
+
+impl Dsc {
+    /// Read a `.dsc`, requiring a good signature from the `keyring`, if there is one.
+    pub fn read<R: BufRead>(from: R, keyring: Option<&Keyring>) -> Result<Dsc, Error> {
+        let (body, signature) = read_signed(from, keyring)?;
+        Ok(Dsc {
+            package: parse_dsc(&body)?,
+            signature,
+        })
+    }
+
+    pub fn source(&self) -> &Source {
+        self.package
+            .as_src()
+            .expect("a Dsc is always built from a Source")
+    }
+
+    /// Check the files listed in the `.dsc` are present in `dir`, with the right sizes and hashes.
+    pub fn verify_files<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        for file in &self.source().files {
+            verify_listed(
+                dir,
+                &file.name,
+                file.size,
+                Some(file.md5),
+                file.sha256,
+                file.sha512,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_dsc(body: &str) -> Result<Package, Error> {
+    let mut map = rfc822::fields_in_block(body.trim()).collect_to_map()?;
+
+    // a `.dsc` names the package `Source`, where the `Sources` index has `Package`
+    ensure!(!map.contains_key("Package"), "unexpected Package in .dsc");
+    let name = map
+        .remove("Source")
+        .ok_or_else(|| anyhow!("no Source in .dsc"))?;
+    map.insert("Package", name);
+    let name = map.get_value("Package").one_line_req()?.to_string();
+
+    let style = PackageType::Source(
+        src::parse_src_in(&mut map, String::new())
+            .with_context(|| anyhow!("source fields in {:?}", name))?,
+    );
+
+    pkg::parse_pkg(&mut map, style).with_context(|| anyhow!("shared fields in {:?}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    use gpgrv::Keyring;
+
+    use super::Dsc;
+    use crate::parse::Signature;
+
+    const DSC: &[u8] = include_bytes!("../../tests/dsc/hello_2.10-3.dsc");
+
+    fn keyring() -> Keyring {
+        let mut keyring = Keyring::new();
+        keyring
+            .append_keys_from_armoured(&include_bytes!("../../tests/dsc/key.asc")[..])
+            .unwrap();
+        keyring
+    }
+
+    #[test]
+    fn signed() {
+        let dsc = Dsc::read(DSC, Some(&keyring())).unwrap();
+        assert_eq!(Signature::Verified, dsc.signature);
+        assert_eq!("hello", dsc.package.name);
+        assert_eq!("2.10-3", dsc.package.version.to_string());
+
+        let source = dsc.source();
+        assert_eq!("", source.directory);
+        assert_eq!(1, source.binaries.len());
+        assert_eq!(2, source.files.len());
+        assert!(source.files.iter().all(|f| f.sha256.is_some()));
+        assert!(dsc.package.unparsed.contains_key("Checksums-Sha1"));
+
+        dsc.verify_files(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/dsc"))
+            .unwrap();
+
+        let tampered = String::from_utf8(DSC.to_vec())
+            .unwrap()
+            .replace("2.10-3", "2.10-4");
+        assert!(Dsc::read(tampered.as_bytes(), Some(&keyring())).is_err());
+        assert!(Dsc::read(tampered.as_bytes(), None).is_ok());
+    }
+
+    #[test]
+    fn unsigned() {
+        let doc = String::from_utf8(DSC.to_vec()).unwrap();
+        let body = doc
+            .split("\n\n")
+            .nth(1)
+            .unwrap()
+            .split("-----BEGIN PGP SIGNATURE-----")
+            .next()
+            .unwrap();
+
+        assert!(Dsc::read(body.as_bytes(), Some(&keyring())).is_err());
+        let dsc = Dsc::read(body.as_bytes(), None).unwrap();
+        assert_eq!(Signature::Unsigned, dsc.signature);
+        assert_eq!("hello", dsc.package.name);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(dsc.verify_files(dir.path()).is_err());
+        for file in &dsc.source().files {
+            fs::write(dir.path().join(&file.name), b"hello, world\n").unwrap();
+        }
+        let err = dsc.verify_files(dir.path()).unwrap_err();
+        assert!(format!("{:?}", err).contains("debian.tar.xz"));
+
+        assert!(Dsc::read(io::Cursor::new(b"Format: 1.0\n"), None).is_err());
+    }
+}
@@ -1,21 +1,74 @@
 //! These types are used to represent a [crate::parse::Package].
 
+/// A fieldless enum, which is parsed from, and displayed as, the given strings.
+macro_rules! strum {
+    ($doc:literal, $name:ident, $($variant:ident($str:expr),)*) => {
+        #[doc = $doc]
+        #[derive(Copy, Clone, Debug, Hash, PartialOrd, Ord, PartialEq, Eq)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+            fn from_str(from: &str) -> Result<$name, Error> {
+                match from {
+                    $($str => Ok($name::$variant), )*
+                    other => bail!("no {}: {:?}", stringify!($name), other),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(match self {
+                    $($name::$variant => $str, )*
+                })
+            }
+        }
+    }
+}
+
 mod arch;
 mod bin;
+mod buildinfo;
+mod changelog;
+mod changes;
+mod control;
 mod deps;
+mod dsc;
 mod ident;
 mod pkg;
 mod src;
+mod status;
+mod upload;
 mod vcs;
+mod version;
 
+pub use self::arch::Abi;
 pub use self::arch::Arch;
 pub use self::arch::Cpu;
-pub use self::arch::Kernel;
+pub use self::arch::Libc;
+pub use self::arch::Os;
+pub use self::arch::Tuple;
 pub use self::bin::Binary;
+pub use self::bin::MultiArch;
+pub use self::buildinfo::BuildInfo;
+pub use self::changelog::Changelog;
+pub use self::changelog::ChangelogEntry;
+pub use self::changes::Changes;
+pub use self::changes::ChangesFile;
+pub use self::control::Control;
+pub use self::control::ControlBinary;
+pub use self::control::ControlSource;
+pub use self::control::Relation;
+pub use self::deps::reduce_dependencies;
 pub use self::deps::Constraint;
 pub use self::deps::ConstraintOperator;
 pub use self::deps::Dependency;
+pub use self::deps::RestrictionList;
 pub use self::deps::SingleDependency;
+pub use self::dsc::Dsc;
 pub use self::ident::Identity;
 pub use self::pkg::Package;
 pub use self::pkg::PackageType;
@@ -24,6 +77,16 @@ pub use self::src::Source;
 pub use self::src::SourceArchive;
 pub use self::src::SourceBinary;
 pub use self::src::SourceFormat;
+pub use self::status::Conffile;
+pub use self::status::Flag;
+pub use self::status::State;
+pub use self::status::Status;
+pub use self::status::Want;
+pub use self::upload::Signature;
+pub use self::upload::Upload;
 pub use self::vcs::Vcs;
 pub use self::vcs::VcsTag;
 pub use self::vcs::VcsType;
+pub use self::version::Version;
+
+pub(crate) use self::upload::parse_source;
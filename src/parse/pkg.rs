@@ -12,6 +12,7 @@ use super::arch;
 use super::bin;
 use super::ident;
 use super::src;
+use super::version::Version;
 use crate::rfc822;
 use crate::rfc822::RfcMapExt;
 
@@ -26,7 +27,7 @@ pub enum PackageType {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Package {
     pub name: String,
-    pub version: String,
+    pub version: Version,
     priority: Option<Priority>,
     pub arches: arch::Arches,
     section: Option<String>,
@@ -48,7 +49,7 @@ pub struct File {
     pub md5: Option<String>,
     pub sha1: String,
     pub sha256: String,
-    pub sha512: String,
+    pub sha512: Option<String>,
 }
 
 /// https://www.debian.org/doc/debian-policy/#priorities
@@ -70,6 +71,16 @@ impl Default for Priority {
 }
 
 impl Package {
+    /// A package for tests, from just the fields they care about.
+    #[cfg(test)]
+    pub(crate) fn parse_test(block: &str) -> Package {
+        let block = format!(
+            "{}\nMaintainer: Someone <someone@example.com>\nDescription: test\n",
+            block
+        );
+        Package::parse(&mut rfc822::fields_in_block(&block).collect_to_map().unwrap()).unwrap()
+    }
+
     pub fn parse(map: &mut rfc822::Map) -> Result<Package, Error> {
         let name = map
             .get_value("Package")
@@ -138,7 +149,7 @@ impl Package {
     }
 }
 
-fn parse_pkg(map: &mut rfc822::Map, style: PackageType) -> Result<Package, Error> {
+pub(super) fn parse_pkg(map: &mut rfc822::Map, style: PackageType) -> Result<Package, Error> {
     let arches = map
         .remove_value("Architecture")
         .one_line_req()?
@@ -157,7 +168,7 @@ fn parse_pkg(map: &mut rfc822::Map, style: PackageType) -> Result<Package, Error
 
     Ok(Package {
         name: map.remove_value("Package").one_line_req()?.to_string(),
-        version: map.remove_value("Version").one_line_req()?.to_string(),
+        version: map.remove_value("Version").one_line_req()?.parse()?,
         priority: None,
         arches,
         section: None,
@@ -165,15 +176,7 @@ fn parse_pkg(map: &mut rfc822::Map, style: PackageType) -> Result<Package, Error
         original_maintainer,
         homepage: map.remove_value("Homepage").one_line_owned()?,
         style,
-        unparsed: map
-            .into_iter()
-            .map(|(k, v)| {
-                (
-                    k.to_string(),
-                    v.into_iter().map(|v| v.to_string()).collect(),
-                )
-            })
-            .collect(),
+        unparsed: rfc822::to_owned_map(map),
     })
 }
 
@@ -45,6 +45,7 @@ pub struct SourceArchive {
     pub size: u64,
     pub md5: crate::checksum::MD5,
     pub sha256: Option<crate::checksum::SHA256>,
+    pub sha512: Option<crate::checksum::SHA512>,
 }
 
 /// Information on the binary packages built from a source package.
@@ -68,11 +69,17 @@ pub enum SourceFormat {
 }
 
 pub(super) fn parse_src(map: &mut rfc822::Map) -> Result<Source, Error> {
+    let directory = map.remove_value("Directory").one_line_req()?.to_string();
+    parse_src_in(map, directory)
+}
+
+/// The source fields, for a package which may not be in an archive, e.g. from a `.dsc`.
+pub(super) fn parse_src_in(map: &mut rfc822::Map, directory: String) -> Result<Source, Error> {
     Ok(Source {
         format: parse_format(map.remove_value("Format").one_line_req()?)?,
         binaries: take_package_list(map)?,
         files: take_files(map)?,
-        directory: map.remove_value("Directory").one_line_req()?.to_string(),
+        directory,
         vcs: super::vcs::extract(map)?,
         // TODO: Option<> instead of empty string?
         standards_version: map
@@ -169,20 +176,25 @@ pub(super) fn take_package_list(map: &mut rfc822::Map) -> Result<Vec<SourceBinar
 pub(super) fn take_files(map: &mut rfc822::Map) -> Result<Vec<SourceArchive>, Error> {
     use crate::checksum::parse_md5;
     use crate::checksum::parse_sha256;
+    use crate::checksum::parse_sha512;
     use crate::release::take_checksums;
     let file_and_size_to_md5 =
         take_checksums(map, "Files")?.ok_or_else(|| anyhow!("Files required"))?;
     let mut file_and_size_to_sha256 =
         take_checksums(map, "Checksums-Sha256")?.unwrap_or_else(HashMap::new);
+    let mut file_and_size_to_sha512 =
+        take_checksums(map, "Checksums-Sha512")?.unwrap_or_else(HashMap::new);
 
     let mut archives = Vec::with_capacity(file_and_size_to_md5.len());
     for ((name, size), md5) in file_and_size_to_md5 {
         let sha256 = file_and_size_to_sha256.remove(&(name, size));
+        let sha512 = file_and_size_to_sha512.remove(&(name, size));
         archives.push(SourceArchive {
             name: name.to_string(),
             size,
             md5: parse_md5(md5)?,
             sha256: sha256.map(|v| parse_sha256(v)).inside_out()?,
+            sha512: sha512.map(parse_sha512).inside_out()?,
         })
     }
 
@@ -192,5 +204,11 @@ pub(super) fn take_files(map: &mut rfc822::Map) -> Result<Vec<SourceArchive>, Er
         file_and_size_to_sha256
     );
 
+    ensure!(
+        file_and_size_to_sha512.is_empty(),
+        "sha512sum for a file which didn't exist: {:?}",
+        file_and_size_to_sha512
+    );
+
     Ok(archives)
 }
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Error;
+
+/// The `Status` field from the `dpkg` database, e.g. `install ok installed`.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Status {
+    pub want: Want,
+    pub flag: Flag,
+    pub state: State,
+}
+
+strum!(
+    "What the administrator has asked for the package, e.g. with `dpkg --set-selections`.",
+    Want,
+    Unknown("unknown"),
+    Install("install"),
+    Hold("hold"),
+    Deinstall("deinstall"),
+    Purge("purge"),
+);
+
+strum!(
+    "Whether the package is broken, i.e. needs reinstalling before it can be removed.",
+    Flag,
+    Ok("ok"),
+    ReinstReq("reinstreq"),
+);
+
+strum!(
+    "How far through being installed (or removed) the package is.",
+    State,
+    NotInstalled("not-installed"),
+    ConfigFiles("config-files"),
+    HalfInstalled("half-installed"),
+    Unpacked("unpacked"),
+    HalfConfigured("half-configured"),
+    TriggersAwaited("triggers-awaited"),
+    TriggersPending("triggers-pending"),
+    Installed("installed"),
+);
+
+/// A configuration file, from the `Conffiles` field, which `dpkg` won't overwrite if edited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conffile {
+    pub path: String,
+    /// The md5 of the file as shipped, or `newconffile` if it hasn't been unpacked yet.
+    pub md5: String,
+    /// No longer shipped by the package, but kept because it was edited.
+    pub obsolete: bool,
+    /// Will be removed when the package is next upgraded.
+    pub remove_on_upgrade: bool,
+    /// Any flags we don't understand, kept in case a newer `dpkg` added them.
+    pub other_flags: Vec<String>,
+}
+
+impl Status {
+    /// Is the package (fully) installed, and configured?
+    pub fn is_installed(&self) -> bool {
+        State::Installed == self.state
+    }
+
+    /// Is the package held, i.e. won't be automatically upgraded?
+    pub fn is_held(&self) -> bool {
+        Want::Hold == self.want
+    }
+}
+
+impl FromStr for Status {
+    type Err = Error;
+
+    fn from_str(from: &str) -> Result<Status, Error> {
+        let parts: Vec<&str> = from.split_whitespace().collect();
+        match parts.as_slice() {
+            [want, flag, state] => Ok(Status {
+                want: want.parse()?,
+                flag: flag.parse()?,
+                state: state.parse()?,
+            }),
+            _ => bail!("expected 'want flag state', not {:?}", from),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.want, self.flag, self.state)
+    }
+}
+
+pub(super) fn parse_conffiles(lines: &[&str]) -> Result<Vec<Conffile>, Error> {
+    lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let (path, md5) = match (parts.next(), parts.next()) {
+                (Some(path), Some(md5)) => (path, md5),
+                _ => bail!("expected 'path md5', not {:?}", line),
+            };
+
+            let mut conffile = Conffile {
+                path: path.to_string(),
+                md5: md5.to_string(),
+                obsolete: false,
+                remove_on_upgrade: false,
+                other_flags: Vec::new(),
+            };
+
+            for flag in parts {
+                match flag {
+                    "obsolete" => conffile.obsolete = true,
+                    "remove-on-upgrade" => conffile.remove_on_upgrade = true,
+                    other => conffile.other_flags.push(other.to_string()),
+                }
+            }
+
+            Ok(conffile)
+        })
+        .collect::<Result<_, Error>>()
+        .with_context(|| anyhow!("parsing Conffiles"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_conffiles;
+    use super::Flag;
+    use super::State;
+    use super::Status;
+    use super::Want;
+
+    #[test]
+    fn status() {
+        let status: Status = "hold reinstreq half-configured".parse().unwrap();
+        assert_eq!(Want::Hold, status.want);
+        assert_eq!(Flag::ReinstReq, status.flag);
+        assert_eq!(State::HalfConfigured, status.state);
+        assert!(status.is_held());
+        assert!(!status.is_installed());
+        assert_eq!("hold reinstreq half-configured", status.to_string());
+
+        assert!("install ok installed"
+            .parse::<Status>()
+            .unwrap()
+            .is_installed());
+        assert!("install ok".parse::<Status>().is_err());
+        assert!("install fine installed".parse::<Status>().is_err());
+    }
+
+    #[test]
+    fn conffiles() {
+        let conffiles = parse_conffiles(&[
+            "",
+            "/etc/foo.conf 0123456789abcdef0123456789abcdef",
+            "/etc/old.conf 0123456789abcdef0123456789abcdef obsolete",
+            "/etc/new.conf newconffile remove-on-upgrade",
+            "/etc/odd.conf 0123456789abcdef0123456789abcdef obsolete from-the-future",
+        ])
+        .unwrap();
+
+        assert_eq!(4, conffiles.len());
+        assert_eq!("/etc/foo.conf", conffiles[0].path);
+        assert!(!conffiles[0].obsolete);
+        assert!(conffiles[1].obsolete);
+        assert_eq!("newconffile", conffiles[2].md5);
+        assert!(conffiles[2].remove_on_upgrade);
+        assert!(conffiles[2].other_flags.is_empty());
+        assert!(conffiles[3].obsolete);
+        assert_eq!(
+            vec!["from-the-future".to_string()],
+            conffiles[3].other_flags
+        );
+
+        assert!(parse_conffiles(&["/etc/foo.conf"]).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+//! What's shared between the files describing an upload: `.dsc`, `.changes` and `.buildinfo`.
+
+use std::io::BufRead;
+
+use anyhow::ensure;
+use anyhow::Error;
+use gpgrv::Keyring;
+
+use super::version::Version;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+use crate::signing::read_maybe_clearsigned;
+
+/// Was a file clearsigned, and was the signature checked?
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Signature {
+    Unsigned,
+    /// Signed, but no keyring was provided, so the signature wasn't checked.
+    Unchecked,
+    /// Signed, by a key from the keyring.
+    Verified,
+}
+
+/// The fields a `.changes` and a `.buildinfo` have in common.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Upload {
+    pub format: String,
+    pub source: String,
+    /// The version of the source, if it's different from `version`, e.g. for a binNMU.
+    pub source_version: Option<Version>,
+    /// Not present for source-only uploads.
+    pub binaries: Vec<String>,
+    /// What's included: `source`, `all`, and/or actual architectures.
+    pub arches: Vec<String>,
+    pub version: Version,
+}
+
+/// Read a file which may be clearsigned, requiring a good signature from the `keyring`,
+/// if there is one.
+pub(super) fn read_signed<R: BufRead>(
+    from: R,
+    keyring: Option<&Keyring>,
+) -> Result<(String, Signature), Error> {
+    let (body, signed) = read_maybe_clearsigned(from, keyring)?;
+    let signature = match (signed, keyring) {
+        (false, _) => Signature::Unsigned,
+        (true, None) => Signature::Unchecked,
+        (true, Some(_)) => Signature::Verified,
+    };
+    Ok((body, signature))
+}
+
+pub(super) fn parse_upload(map: &mut rfc822::Map) -> Result<Upload, Error> {
+    let (source, source_version) = parse_source(map.remove_value("Source").one_line_req()?)?;
+
+    Ok(Upload {
+        format: map.remove_value("Format").one_line_req()?.to_string(),
+        source,
+        source_version,
+        binaries: map
+            .remove_value("Binary")
+            .one_line()?
+            .map(|line| line.split_whitespace().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+        arches: map.remove_value("Architecture").split_whitespace()?,
+        version: map.remove_value("Version").one_line_req()?.parse()?,
+    })
+}
+
+/// `hello`, or `hello (2.10-3)`.
+pub(crate) fn parse_source(line: &str) -> Result<(String, Option<Version>), Error> {
+    Ok(match line.find(" (") {
+        Some(open) => {
+            ensure!(line.ends_with(')'), "invalid Source: {:?}", line);
+            (
+                line[..open].to_string(),
+                Some(line[open + 2..line.len() - 1].parse()?),
+            )
+        }
+        None => (line.to_string(), None),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_source;
+
+    #[test]
+    fn source() {
+        assert_eq!(("hello".to_string(), None), parse_source("hello").unwrap());
+        let (name, version) = parse_source("hello (2.10-3)").unwrap();
+        assert_eq!("hello", name);
+        assert_eq!("2.10-3", version.unwrap().to_string());
+        assert!(parse_source("hello (2.10-3").is_err());
+    }
+}
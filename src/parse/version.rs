@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+
+/// A Debian package version, e.g. `1:2.30-1ubuntu3`: `epoch:upstream-revision`.
+///
+/// Versions compare, and are equal, the way `dpkg --compare-versions` sees them,
+/// so e.g. `1.0`, `0:1.0` and `1.00-0` are all the same version.
+#[derive(Clone)]
+pub struct Version {
+    text: String,
+    epoch: u32,
+    /// Where the upstream version starts and ends in `text`.
+    upstream: (usize, usize),
+}
+
+impl Version {
+    /// The epoch, or zero if there isn't one.
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// The upstream part of the version, e.g. `2.30`.
+    pub fn upstream(&self) -> &str {
+        &self.text[self.upstream.0..self.upstream.1]
+    }
+
+    /// The Debian revision, e.g. `1ubuntu3`, if there is one (i.e. the package isn't native).
+    pub fn revision(&self) -> Option<&str> {
+        if self.upstream.1 == self.text.len() {
+            None
+        } else {
+            Some(&self.text[self.upstream.1 + 1..])
+        }
+    }
+
+    /// The version exactly as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Version, Error> {
+        parse(text).with_context(|| anyhow!("parsing version {:?}", text))
+    }
+}
+
+fn parse(text: &str) -> Result<Version, Error> {
+    ensure!(!text.is_empty(), "version is empty");
+
+    let (epoch, start) = match text.find(':') {
+        Some(colon) => {
+            let epoch = &text[..colon];
+            ensure!(
+                !epoch.is_empty() && epoch.chars().all(|c| c.is_ascii_digit()),
+                "epoch isn't a number"
+            );
+            (epoch.parse()?, colon + 1)
+        }
+        None => (0, 0),
+    };
+
+    let end = text
+        .rfind('-')
+        .filter(|&dash| dash > start)
+        .unwrap_or(text.len());
+
+    let upstream = &text[start..end];
+    ensure!(!upstream.is_empty(), "upstream version is empty");
+    ensure!(
+        upstream
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".+~-:".contains(c)),
+        "invalid character in upstream version"
+    );
+
+    if end != text.len() {
+        let revision = &text[end + 1..];
+        ensure!(!revision.is_empty(), "revision is empty");
+        ensure!(
+            revision
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || ".+~".contains(c)),
+            "invalid character in revision"
+        );
+    }
+
+    Ok(Version {
+        text: text.to_string(),
+        epoch,
+        upstream: (start, end),
+    })
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| verrevcmp(self.upstream(), other.upstream()))
+            .then_with(|| {
+                verrevcmp(
+                    self.revision().unwrap_or(""),
+                    other.revision().unwrap_or(""),
+                )
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Version) -> bool {
+        Ordering::Equal == self.cmp(other)
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.epoch.hash(state);
+        normalise(self.upstream()).hash(state);
+        normalise(self.revision().unwrap_or("")).hash(state);
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl fmt::Debug for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Version({:?})", self.text)
+    }
+}
+
+/// The sort order of a character in the non-digit part of a version, as in dpkg.
+fn order(c: Option<u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+/// dpkg's `verrevcmp`: alternately compare non-digit runs (lexically, but with `~` first,
+/// then letters, then everything else), and digit runs (numerically).
+fn verrevcmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let ac = order(a.get(i).copied());
+            let bc = order(b.get(j).copied());
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while i < a.len() && b'0' == a[i] {
+            i += 1;
+        }
+        while j < b.len() && b'0' == b[j] {
+            j += 1;
+        }
+
+        let mut first_diff = Ordering::Equal;
+        while i < a.len() && a[i].is_ascii_digit() && j < b.len() && b[j].is_ascii_digit() {
+            if Ordering::Equal == first_diff {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        if i < a.len() && a[i].is_ascii_digit() {
+            return Ordering::Greater;
+        }
+        if j < b.len() && b[j].is_ascii_digit() {
+            return Ordering::Less;
+        }
+        if Ordering::Equal != first_diff {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// A form of a version part which is the same for all parts `verrevcmp` considers equal,
+/// i.e. with leading zeros removed from every number, and a missing number being zero.
+fn normalise(part: &str) -> String {
+    let mut ret = String::with_capacity(part.len());
+    let mut rest = part;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        ret.push_str(&rest[..digits]);
+        rest = &rest[digits..];
+
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let number = rest[..end].trim_start_matches('0');
+        ret.push_str(if number.is_empty() { "0" } else { number });
+        rest = &rest[end..];
+    }
+
+    if "0" == ret {
+        ret.clear();
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::collections::HashSet;
+
+    use super::Version;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parts() {
+        let version = v("1:2.30-1ubuntu3");
+        assert_eq!(1, version.epoch());
+        assert_eq!("2.30", version.upstream());
+        assert_eq!(Some("1ubuntu3"), version.revision());
+
+        let version = v("2.0-rc1-3");
+        assert_eq!(0, version.epoch());
+        assert_eq!("2.0-rc1", version.upstream());
+        assert_eq!(Some("3"), version.revision());
+
+        assert_eq!(None, v("7").revision());
+        assert_eq!("1:2-3", v("1:2-3").to_string());
+
+        for bad in &["", "a:1", ":1", "1:", "1-", "1 2", "1-a-b_c", "1-2:3"] {
+            assert!(bad.parse::<Version>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn ordering() {
+        for (left, right, expected) in &[
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "0:1.0-0", Ordering::Equal),
+            ("1.00", "1.0", Ordering::Equal),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0", "1.0+b1", Ordering::Less),
+            ("1.0", "1.0.1", Ordering::Less),
+            ("1.0a", "1.0+", Ordering::Less),
+            ("1:0.1", "2.0", Ordering::Greater),
+            ("1.10", "1.9", Ordering::Greater),
+            ("7.66+dfsg-5", "7.66+dfsg-10", Ordering::Less),
+            ("1.0-1~bpo1", "1.0-1", Ordering::Less),
+        ] {
+            assert_eq!(*expected, v(left).cmp(&v(right)), "{} vs {}", left, right);
+        }
+    }
+
+    #[test]
+    fn hash_agrees() {
+        let versions: HashSet<Version> = ["1.0", "0:1.0", "1.00-0", "1.0-0", "1.0a0", "1.0a"]
+            .iter()
+            .map(|s| v(s))
+            .collect();
+        assert_eq!(2, versions.len());
+    }
+}
@@ -0,0 +1,525 @@
+//! Incremental updates of _Listings_, by applying `ed`-style patches ("pdiffs")
+//! to a version of the file we already have.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::str;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use flate2::bufread::GzDecoder;
+use reqwest::Client;
+use tempfile_fast::PersistableTempFile;
+
+use crate::checksum;
+use crate::checksum::SHA256;
+use crate::fetch;
+use crate::lists::DownloadableListing;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+
+/// The contents of a `Packages.diff/Index` file.
+#[derive(Debug, Clone)]
+struct DiffIndex {
+    current: SHA256,
+    /// The file as it was before each patch was published.
+    history: Vec<DiffEntry>,
+    /// The uncompressed patches.
+    patches: Vec<DiffEntry>,
+    /// The patches as they are on the mirror, i.e. with a `.gz` suffix.
+    download: Vec<DiffEntry>,
+    /// Each patch goes directly to the current version, instead of to the next patch.
+    merged: bool,
+}
+
+#[derive(Debug, Clone)]
+struct DiffEntry {
+    sha256: SHA256,
+    name: String,
+}
+
+/// Try and bring a _Listing_ up to date by patching an old version from the `lists_dir`.
+///
+/// Returns `false` if we don't have any version which the patches apply to.
+pub async fn update<P: AsRef<Path>, Q: AsRef<Path>>(
+    client: &Client,
+    lists_dir: P,
+    temp_dir: Q,
+    list: &DownloadableListing,
+) -> Result<bool, Error> {
+    let lists_dir = lists_dir.as_ref();
+    let temp_dir = temp_dir.as_ref();
+    let local_name = list.local_name();
+
+    // The patched file can't be checked without this, so just download it.
+    let decompressed_hashes = match list.decompressed_hashes {
+        Some(hashes) => hashes,
+        None => return Ok(false),
+    };
+
+    let location = list
+        .diff_index
+        .as_ref()
+        .ok_or_else(|| anyhow!("no diff index for {}", list.url))?;
+
+    let index_path = temp_dir.join(format!("{}.diff-Index", local_name));
+    fetch::fetch(
+        client.clone(),
+        vec![fetch::Download::from_to(location.url.clone(), &index_path)],
+    )
+    .await
+    .with_context(|| anyhow!("downloading diff index"))?;
+
+    let index = fs::read_to_string(&index_path)?;
+    checksum::validate(io::Cursor::new(&index), location.hashes)
+        .with_context(|| anyhow!("validating diff index {:?}", index_path))?;
+    let index = parse_index(&index).with_context(|| anyhow!("parsing {}", location.url))?;
+
+    ensure!(
+        decompressed_hashes.sha256 == index.current,
+        "diff index isn't for the version in the release"
+    );
+
+    let start = match index
+        .history
+        .iter()
+        .position(|entry| lists_dir.join(hex::encode(entry.sha256)).exists())
+    {
+        Some(start) => start,
+        None => return Ok(false),
+    };
+
+    let needed = if index.merged {
+        &index.history[start..=start]
+    } else {
+        &index.history[start..]
+    };
+
+    let mut downloads = Vec::with_capacity(needed.len());
+    for entry in needed {
+        downloads.push(fetch::Download::from_to(
+            location.url.join(&format!("{}.gz", entry.name))?,
+            temp_dir.join(format!("{}.{}.gz", local_name, entry.name)),
+        ));
+    }
+
+    fetch::fetch(client.clone(), downloads)
+        .await
+        .with_context(|| anyhow!("downloading patches"))?;
+
+    let base = fs::read(lists_dir.join(hex::encode(index.history[start].sha256)))?;
+    let mut lines = split_lines(&base);
+
+    for entry in needed {
+        let patch = read_patch(
+            &index,
+            entry,
+            temp_dir.join(format!("{}.{}.gz", local_name, entry.name)),
+        )
+        .with_context(|| anyhow!("reading patch {:?}", entry.name))?;
+        apply_ed(&mut lines, &patch).with_context(|| anyhow!("applying patch {:?}", entry.name))?;
+    }
+
+    let mut patched = PersistableTempFile::new_in(lists_dir)
+        .with_context(|| anyhow!("making temporary file in {:?}", lists_dir))?;
+    {
+        let mut writer = io::BufWriter::new(&mut patched);
+        for line in &lines {
+            writer.write_all(line)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+    }
+
+    patched.seek(SeekFrom::Start(0))?;
+    checksum::validate(&mut patched, decompressed_hashes)
+        .with_context(|| anyhow!("validating patched file"))?;
+
+    patched
+        .persist_by_rename(lists_dir.join(local_name))
+        .map_err(|e| e.error)
+        .with_context(|| anyhow!("storing patched file"))?;
+
+    Ok(true)
+}
+
+fn read_patch<P: AsRef<Path>>(
+    index: &DiffIndex,
+    entry: &DiffEntry,
+    path: P,
+) -> Result<Vec<u8>, Error> {
+    let compressed_name = format!("{}.gz", entry.name);
+    let compressed = find_entry(&index.download, &compressed_name)?;
+    let uncompressed = find_entry(&index.patches, &entry.name)?;
+
+    checksum::validate_sha256(fs::File::open(path.as_ref())?, compressed.sha256)
+        .with_context(|| anyhow!("validating downloaded patch"))?;
+
+    let mut patch = Vec::new();
+    GzDecoder::new(io::BufReader::new(fs::File::open(path.as_ref())?))
+        .read_to_end(&mut patch)
+        .with_context(|| anyhow!("decompressing"))?;
+
+    checksum::validate_sha256(io::Cursor::new(&patch), uncompressed.sha256)
+        .with_context(|| anyhow!("validating decompressed patch"))?;
+
+    Ok(patch)
+}
+
+fn find_entry<'e>(entries: &'e [DiffEntry], name: &str) -> Result<&'e DiffEntry, Error> {
+    entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| anyhow!("{:?} not listed in diff index", name))
+}
+
+fn parse_index(index: &str) -> Result<DiffIndex, Error> {
+    let mut data = rfc822::fields_in_block(index).collect_to_map()?;
+
+    let current = data.remove_value("SHA256-Current").one_line_req()?;
+    let current = checksum::parse_sha256(
+        current
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("empty SHA256-Current"))?,
+    )?;
+
+    Ok(DiffIndex {
+        current,
+        history: parse_entries(&data.remove("SHA256-History").unwrap_or_default())?,
+        patches: parse_entries(&data.remove("SHA256-Patches").unwrap_or_default())?,
+        download: parse_entries(&data.remove("SHA256-Download").unwrap_or_default())?,
+        merged: Some("merged") == data.remove_value("X-Patch-Precedence").one_line()?,
+    })
+}
+
+fn parse_entries(lines: &[&str]) -> Result<Vec<DiffEntry>, Error> {
+    let mut ret = Vec::with_capacity(lines.len());
+    for line in lines {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        ensure!(3 == parts.len(), "invalid diff index line: {:?}", line);
+        ret.push(DiffEntry {
+            sha256: checksum::parse_sha256(parts[0])?,
+            name: parts[2].to_string(),
+        });
+    }
+    Ok(ret)
+}
+
+fn split_lines(file: &[u8]) -> Vec<Vec<u8>> {
+    let mut lines: Vec<Vec<u8>> = file.split(|&b| b'\n' == b).map(|l| l.to_vec()).collect();
+    // the trailing new-line doesn't start a line
+    if lines.last().map(|l| l.is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Apply the subset of `ed` commands generated by `diff --ed`.
+fn apply_ed(lines: &mut Vec<Vec<u8>>, patch: &[u8]) -> Result<(), Error> {
+    let mut script = split_lines(patch).into_iter();
+
+    // ed's "current line", 1-indexed, which some commands default to
+    let mut current = 0;
+
+    while let Some(command) = script.next() {
+        let command = str::from_utf8(&command)?;
+
+        // diff can't write a line containing just a `.`, so it writes `..`, then fixes it
+        if "s/.//" == command {
+            ensure!(
+                current >= 1 && current <= lines.len(),
+                "substitution outside of file"
+            );
+            let line = &mut lines[current - 1];
+            ensure!(Some(&b'.') == line.first(), "substitution didn't match");
+            line.remove(0);
+            continue;
+        }
+
+        ensure!(!command.is_empty(), "empty command");
+        let (range, op) = command.split_at(command.len() - 1);
+        let (start, end) = match range.find(',') {
+            Some(comma) => (range[..comma].parse()?, range[comma + 1..].parse()?),
+            None if range.is_empty() => (current, current),
+            None => {
+                let line = range.parse()?;
+                (line, line)
+            }
+        };
+
+        ensure!(
+            start <= end && end <= lines.len(),
+            "invalid range in {:?}",
+            command
+        );
+
+        match op {
+            "a" => {
+                let text = read_text(&mut script)?;
+                current = end + text.len();
+                lines.splice(end..end, text);
+            }
+            "c" => {
+                ensure!(start >= 1, "invalid range in {:?}", command);
+                let text = read_text(&mut script)?;
+                current = start - 1 + text.len();
+                lines.splice(start - 1..end, text);
+            }
+            "d" => {
+                ensure!(start >= 1, "invalid range in {:?}", command);
+                lines.drain(start - 1..end);
+                current = start.min(lines.len());
+            }
+            other => bail!("unsupported ed command: {:?}", other),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_text<I: Iterator<Item = Vec<u8>>>(script: &mut I) -> Result<Vec<Vec<u8>>, Error> {
+    let mut ret = Vec::new();
+    loop {
+        let line = script
+            .next()
+            .ok_or_else(|| anyhow!("unterminated text in patch"))?;
+        if b"." == line.as_slice() {
+            return Ok(ret);
+        }
+        ret.push(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression as GzLevel;
+    use reqwest::Client;
+    use reqwest::Url;
+    use sha2::Digest;
+    use sha2::Sha256;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::apply_ed;
+    use super::parse_index;
+    use super::split_lines;
+    use super::update;
+    use crate::checksum::Hashes;
+    use crate::checksum::SHA256;
+    use crate::lists::Compression;
+    use crate::lists::DiffIndexLocation;
+    use crate::lists::DownloadableListing;
+
+    fn patched(base: &str, patch: &str) -> String {
+        let mut lines = split_lines(base.as_bytes());
+        apply_ed(&mut lines, patch.as_bytes()).unwrap();
+        lines
+            .into_iter()
+            .map(|l| String::from_utf8(l).unwrap() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn ed() {
+        assert_eq!(
+            "a\nB\nC\nd\nf\ng\n",
+            patched("a\nb\nc\nd\ne\nf\n", "6a\ng\n.\n5d\n2,3c\nB\nC\n.\n")
+        );
+        assert_eq!("z\na\n", patched("a\n", "0a\nz\n.\n"));
+    }
+
+    #[test]
+    fn ed_dot_line() {
+        assert_eq!(
+            "a\n.\nb\nc\n",
+            patched("a\nc\n", "1a\n..\n.\ns/.//\na\nb\n.\n")
+        );
+    }
+
+    #[test]
+    fn index() {
+        let index = parse_index(
+            "SHA256-Current: 9e1e3b5b8f6d4b9c0e8f8d2a3b0b1c6a9c0c3b6d4a1e2f3a4b5c6d7e8f9a0b1c 1234
+SHA256-History:
+ 1111111111111111111111111111111111111111111111111111111111111111 1200 2019-02-06-1429.43
+ 2222222222222222222222222222222222222222222222222222222222222222 1220 2019-02-06-2029.12
+SHA256-Patches:
+ 3333333333333333333333333333333333333333333333333333333333333333 40 2019-02-06-1429.43
+ 4444444444444444444444444444444444444444444444444444444444444444 20 2019-02-06-2029.12
+SHA256-Download:
+ 5555555555555555555555555555555555555555555555555555555555555555 60 2019-02-06-1429.43.gz
+ 6666666666666666666666666666666666666666666666666666666666666666 40 2019-02-06-2029.12.gz
+X-Patch-Precedence: merged
+",
+        )
+        .unwrap();
+        assert_eq!(2, index.history.len());
+        assert_eq!([0x22; 32], index.history[1].sha256);
+        assert_eq!("2019-02-06-2029.12.gz", index.download[1].name);
+        assert!(index.merged);
+    }
+
+    fn sha256(data: &[u8]) -> SHA256 {
+        Sha256::digest(data).into()
+    }
+
+    fn hashes(data: &[u8]) -> Hashes {
+        Hashes {
+            md5: [0; 16],
+            sha256: sha256(data),
+        }
+    }
+
+    /// Serve these files, by path, until the test ends.
+    async fn serve(files: HashMap<String, Vec<u8>>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| b"\r\n\r\n" == w) {
+                    let read = conn.read(&mut buf).await.unwrap();
+                    if 0 == read {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let response = match files.get(path) {
+                    Some(body) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    }
+                    None => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                conn.write_all(&response).await.unwrap();
+                conn.shutdown().await.unwrap();
+            }
+        });
+        Url::parse(&format!("http://{}/", addr)).unwrap()
+    }
+
+    /// Serve a diff index with a single `patch` from `old` to `new`,
+    /// and return the listing the release would describe for `new`.
+    async fn listing(old: &[u8], patch: &[u8], new: &[u8]) -> DownloadableListing {
+        let mut compressed = GzEncoder::new(Vec::new(), GzLevel::default());
+        compressed.write_all(patch).unwrap();
+        let compressed = compressed.finish().unwrap();
+
+        let index = format!(
+            "SHA256-Current: {} {}
+SHA256-History:
+ {} {} 2019-02-06-1429.43
+SHA256-Patches:
+ {} {} 2019-02-06-1429.43
+SHA256-Download:
+ {} {} 2019-02-06-1429.43.gz
+",
+            hex::encode(sha256(new)),
+            new.len(),
+            hex::encode(sha256(old)),
+            old.len(),
+            hex::encode(sha256(patch)),
+            patch.len(),
+            hex::encode(sha256(&compressed)),
+            compressed.len(),
+        );
+
+        let mut files = HashMap::new();
+        files.insert(
+            "/main/binary-amd64/Packages.diff/Index".to_string(),
+            index.clone().into_bytes(),
+        );
+        files.insert(
+            "/main/binary-amd64/Packages.diff/2019-02-06-1429.43.gz".to_string(),
+            compressed,
+        );
+        let base = serve(files).await;
+
+        DownloadableListing {
+            url: base.join("main/binary-amd64/Packages").unwrap(),
+            codec: Compression::None,
+            compressed_hashes: hashes(new),
+            decompressed_hashes: Some(hashes(new)),
+            diff_index: Some(DiffIndexLocation {
+                url: base.join("main/binary-amd64/Packages.diff/Index").unwrap(),
+                hashes: hashes(index.as_bytes()),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_listing() {
+        let old = b"Package: a\n\nPackage: b\n";
+        let new = b"Package: a\n\nPackage: c\n";
+        let patch = b"3c\nPackage: c\n.\n";
+
+        let lists_dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let client = Client::new();
+
+        let list = listing(old, patch, new).await;
+
+        // we don't have anything to patch yet
+        assert!(!update(&client, lists_dir.path(), temp_dir.path(), &list)
+            .await
+            .unwrap());
+
+        fs::write(lists_dir.path().join(hex::encode(sha256(old))), old).unwrap();
+        assert!(update(&client, lists_dir.path(), temp_dir.path(), &list)
+            .await
+            .unwrap());
+        assert_eq!(
+            &new[..],
+            fs::read(lists_dir.path().join(list.local_name()))
+                .unwrap()
+                .as_slice()
+        );
+    }
+
+    #[tokio::test]
+    async fn update_listing_mismatch() {
+        let old = b"Package: a\n\nPackage: b\n";
+        let new = b"Package: a\n\nPackage: c\n";
+        // a consistent index, but the patch doesn't produce what the release promised
+        let patch = b"3c\nPackage: d\n.\n";
+
+        let lists_dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let client = Client::new();
+
+        let list = listing(old, patch, new).await;
+        fs::write(lists_dir.path().join(hex::encode(sha256(old))), old).unwrap();
+
+        assert!(update(&client, lists_dir.path(), temp_dir.path(), &list)
+            .await
+            .is_err());
+        assert!(!lists_dir.path().join(list.local_name()).exists());
+    }
+}
@@ -0,0 +1,636 @@
+//! Choose which version of a package would be installed, like `apt-cache policy`,
+//! following the pins in `/etc/apt/preferences` (see `apt_preferences(5)`).
+//!
+//! Every version gets a priority, from the _Releases_ it is available from, and any pins.
+//! The candidate is the version with the highest priority, or the newest if there's a tie,
+//! but a version is never downgraded unless a pin gives the replacement at least `1000`.
+
+use std::io::BufRead;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Error;
+use regex::Regex;
+
+use crate::parse::Version;
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
+use crate::system::DownloadedList;
+
+/// The priority of a version in a `NotAutomatic` _Release_, e.g. `experimental`.
+pub const NOT_AUTOMATIC_PRIORITY: i32 = 1;
+/// The priority of a version which is only installed, not available from anywhere,
+/// or in a `NotAutomatic` but `ButAutomaticUpgrades` _Release_, e.g. `-backports`.
+pub const INSTALLED_PRIORITY: i32 = 100;
+/// The priority of a version in a normal _Release_.
+pub const DEFAULT_PRIORITY: i32 = 500;
+/// The priority of a version in the target release, e.g. `apt -t unstable`.
+pub const TARGET_PRIORITY: i32 = 990;
+
+/// The pins from some `preferences` files.
+#[derive(Clone, Debug, Default)]
+pub struct Preferences {
+    pub pins: Vec<Pin>,
+}
+
+/// One stanza from a `preferences` file.
+#[derive(Clone, Debug)]
+pub struct Pin {
+    /// The `Package` field; the pin is "general" if this is just `*`.
+    pub packages: Vec<Pattern>,
+    pub target: PinTarget,
+    pub priority: i32,
+}
+
+/// What a `Pin:` line is selecting.
+#[derive(Clone, Debug)]
+pub enum PinTarget {
+    /// `Pin: version 1.2*`
+    Version(Pattern),
+    /// `Pin: release a=unstable, c=main`: all of the conditions must match.
+    Release(Vec<(ReleaseKey, Pattern)>),
+    /// `Pin: origin deb.debian.org`: the host the _Release_ was downloaded from.
+    Origin(Pattern),
+}
+
+/// The properties of a _Release_ that a `Pin: release` can match.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReleaseKey {
+    /// `a=`, the suite, e.g. `unstable`.
+    Archive,
+    /// `n=`, e.g. `sid`.
+    Codename,
+    /// `v=`, e.g. `12`.
+    Version,
+    /// `o=`, e.g. `Debian`.
+    Origin,
+    /// `l=`, e.g. `Debian-Security`.
+    Label,
+    /// `c=`, e.g. `main`.
+    Component,
+    /// `b=`, e.g. `amd64`.
+    Architecture,
+}
+
+/// A package name, version, or release property, as written in a `preferences` file:
+/// an exact string, a glob (`lib*`), or a regex (`/^lib.*$/`).
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Exact(String),
+    Glob(String, Regex),
+    Regex(Regex),
+}
+
+/// Somewhere a version of a package is available from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Origin {
+    pub archive: Option<String>,
+    pub codename: Option<String>,
+    pub version: Option<String>,
+    pub origin: Option<String>,
+    pub label: Option<String>,
+    pub component: Option<String>,
+    pub arch: Option<String>,
+    pub not_automatic: bool,
+    pub but_automatic_upgrades: bool,
+    /// The host the _Release_ was downloaded from, or empty for the `dpkg` status file.
+    pub site: String,
+    /// Is this the `dpkg` status file, i.e. is this version installed?
+    pub installed: bool,
+}
+
+/// A version of a package, and everywhere it is available from.
+#[derive(Clone, Debug)]
+pub struct Available {
+    pub version: Version,
+    pub origins: Vec<Origin>,
+}
+
+/// Priorities, and candidates, for versions of packages.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    preferences: Preferences,
+    target_release: Option<String>,
+}
+
+impl Preferences {
+    /// Parse a `preferences` file, which is `rfc822`-style stanzas, separated by blank lines.
+    pub fn parse<R: BufRead>(from: R) -> Result<Preferences, Error> {
+        let mut pins = Vec::new();
+        for (start, stanza) in rfc822::commented_blocks(from)? {
+            pins.push(
+                Pin::parse(&stanza)
+                    .with_context(|| anyhow!("parsing stanza at line {}", start + 1))?,
+            );
+        }
+
+        Ok(Preferences { pins })
+    }
+
+    /// Add the pins from another file, e.g. one from `preferences.d`, after these.
+    pub fn extend(&mut self, other: Preferences) {
+        self.pins.extend(other.pins);
+    }
+}
+
+impl Pin {
+    fn parse(stanza: &str) -> Result<Pin, Error> {
+        let mut map = rfc822::fields_in_block(stanza).collect_to_map()?;
+
+        let packages = map
+            .remove_value("Package")
+            .one_line_req()?
+            .split_whitespace()
+            .map(Pattern::new)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let target = PinTarget::parse(map.remove_value("Pin").one_line_req()?)?;
+
+        let priority = map.remove_value("Pin-Priority").one_line_req()?;
+        let priority = priority
+            .parse()
+            .with_context(|| anyhow!("invalid Pin-Priority: {:?}", priority))?;
+
+        Ok(Pin {
+            packages,
+            target,
+            priority,
+        })
+    }
+
+    /// Does this pin apply to every package, i.e. is it `Package: *`?
+    pub fn is_general(&self) -> bool {
+        self.packages
+            .iter()
+            .all(|p| matches!(p, Pattern::Glob(glob, _) if "*" == glob))
+    }
+
+    fn matches_package(&self, name: &str) -> bool {
+        self.packages.iter().any(|p| p.matches(name))
+    }
+}
+
+impl PinTarget {
+    fn parse(value: &str) -> Result<PinTarget, Error> {
+        let value = value.trim();
+        let (kind, rest) = value
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("expected e.g. 'release a=stable', not {:?}", value))?;
+        let rest = rest.trim();
+
+        Ok(match kind {
+            "version" => PinTarget::Version(Pattern::new(rest)?),
+            "origin" => PinTarget::Origin(Pattern::new(unquote(rest))?),
+            "release" => {
+                let mut conditions = Vec::new();
+                for condition in rest.split(',') {
+                    let condition = condition.trim();
+                    let (key, value) = match condition.split_once('=') {
+                        Some((key, value)) => (ReleaseKey::from_letter(key.trim())?, value),
+                        // apt's shorthand for `a=`
+                        None => (ReleaseKey::Archive, condition),
+                    };
+                    conditions.push((key, Pattern::new(unquote(value.trim()))?));
+                }
+                PinTarget::Release(conditions)
+            }
+            other => bail!("unsupported Pin type: {:?}", other),
+        })
+    }
+
+    /// Does this select this version from this origin?
+    fn matches(&self, version: &Version, origin: &Origin) -> bool {
+        match self {
+            PinTarget::Version(pattern) => pattern.matches(version.as_str()),
+            PinTarget::Origin(pattern) => pattern.matches(&origin.site),
+            PinTarget::Release(conditions) => {
+                !origin.installed
+                    && conditions.iter().all(|(key, pattern)| {
+                        origin
+                            .get(*key)
+                            .map(|value| pattern.matches(value))
+                            .unwrap_or(false)
+                    })
+            }
+        }
+    }
+}
+
+impl ReleaseKey {
+    fn from_letter(letter: &str) -> Result<ReleaseKey, Error> {
+        use self::ReleaseKey::*;
+        Ok(match letter {
+            "a" => Archive,
+            "n" => Codename,
+            "v" => Version,
+            "o" => Origin,
+            "l" => Label,
+            "c" => Component,
+            "b" => Architecture,
+            other => bail!("unrecognised release property: {:?}", other),
+        })
+    }
+}
+
+impl Pattern {
+    /// Interpret a value from a `preferences` file: `/regex/`, a glob, or a plain string.
+    pub fn new(text: &str) -> Result<Pattern, Error> {
+        if text.len() > 1 && text.starts_with('/') && text.ends_with('/') {
+            let inner = &text[1..text.len() - 1];
+            return Ok(Pattern::Regex(
+                Regex::new(inner).with_context(|| anyhow!("invalid regex: {:?}", text))?,
+            ));
+        }
+
+        if text.contains(|c| "*?[".contains(c)) {
+            return Ok(Pattern::Glob(text.to_string(), glob_to_regex(text)?));
+        }
+
+        Ok(Pattern::Exact(text.to_string()))
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Exact(text) => text == value,
+            Pattern::Glob(_, regex) | Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Translate a shell-style glob (`*`, `?`, `[a-z]`) into an anchored regex.
+fn glob_to_regex(glob: &str) -> Result<Regex, Error> {
+    let mut regex = String::with_capacity(glob.len() * 2);
+    regex.push('^');
+
+    let mut in_class = false;
+    for c in glob.chars() {
+        match c {
+            '[' if !in_class => {
+                in_class = true;
+                regex.push('[');
+            }
+            ']' if in_class => {
+                in_class = false;
+                regex.push(']');
+            }
+            '!' if in_class && regex.ends_with('[') => regex.push('^'),
+            _ if in_class => regex.push(c),
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    ensure!(!in_class, "unterminated [ in {:?}", glob);
+
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+impl Origin {
+    /// Where the packages in this `DownloadedList` came from.
+    pub fn from_list(list: &DownloadedList) -> Origin {
+        let file = &list.release.file;
+        Origin {
+            archive: file
+                .suite
+                .clone()
+                .or_else(|| Some(list.release.req.codename.clone())),
+            codename: file.codename.clone(),
+            version: file.version.clone(),
+            origin: Some(file.origin.clone()),
+            label: Some(file.label.clone()),
+            component: Some(list.listing.component.clone()),
+            arch: list.listing.arch.clone(),
+            not_automatic: file.not_automatic,
+            but_automatic_upgrades: file.but_automatic_upgrades,
+            site: list.release.req.mirror.host_str().unwrap_or("").to_string(),
+            installed: false,
+        }
+    }
+
+    /// The `dpkg` status file, i.e. this version is installed.
+    pub fn installed() -> Origin {
+        Origin {
+            installed: true,
+            ..Origin::default()
+        }
+    }
+
+    fn get(&self, key: ReleaseKey) -> Option<&str> {
+        use self::ReleaseKey::*;
+        match key {
+            Archive => self.archive.as_deref(),
+            Codename => self.codename.as_deref(),
+            Version => self.version.as_deref(),
+            Origin => self.origin.as_deref(),
+            Label => self.label.as_deref(),
+            Component => self.component.as_deref(),
+            Architecture => self.arch.as_deref(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn new(preferences: Preferences) -> Policy {
+        Policy {
+            preferences,
+            target_release: None,
+        }
+    }
+
+    /// Prefer versions from this release (matched by suite or codename), like `apt -t`.
+    pub fn set_target_release(&mut self, release: Option<String>) {
+        self.target_release = release;
+    }
+
+    /// The priority of an origin, ignoring all pins.
+    pub fn default_priority(&self, origin: &Origin) -> i32 {
+        if origin.installed {
+            return INSTALLED_PRIORITY;
+        }
+
+        if let Some(target) = &self.target_release {
+            if origin.archive.as_ref() == Some(target) || origin.codename.as_ref() == Some(target) {
+                return TARGET_PRIORITY;
+            }
+        }
+
+        match (origin.not_automatic, origin.but_automatic_upgrades) {
+            (true, true) => INSTALLED_PRIORITY,
+            (true, false) => NOT_AUTOMATIC_PRIORITY,
+            (false, _) => DEFAULT_PRIORITY,
+        }
+    }
+
+    /// The priority of a version of a package, available from these origins.
+    ///
+    /// The first pin specific to the package wins. Otherwise, the version gets the
+    /// best of its origins' priorities, from the first matching general pin, or the default.
+    pub fn priority(&self, package: &str, version: &Version, origins: &[Origin]) -> i32 {
+        let specific = self.preferences.pins.iter().find(|pin| {
+            !pin.is_general()
+                && pin.matches_package(package)
+                && origins.iter().any(|o| pin.target.matches(version, o))
+        });
+
+        if let Some(pin) = specific {
+            return pin.priority;
+        }
+
+        origins
+            .iter()
+            .map(|origin| {
+                self.preferences
+                    .pins
+                    .iter()
+                    .find(|pin| pin.is_general() && pin.target.matches(version, origin))
+                    .map(|pin| pin.priority)
+                    .unwrap_or_else(|| self.default_priority(origin))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The version which would be installed, if any, from these versions of a package.
+    pub fn candidate<'a>(&self, package: &str, versions: &'a [Available]) -> Option<&'a Available> {
+        let priority =
+            |available: &Available| self.priority(package, &available.version, &available.origins);
+
+        let installed = versions
+            .iter()
+            .find(|available| available.origins.iter().any(|o| o.installed))
+            .map(|available| &available.version);
+
+        versions
+            .iter()
+            .filter(|available| {
+                let priority = priority(available);
+                match installed {
+                    Some(installed) if *installed == available.version => true,
+                    Some(installed) if available.version < *installed => priority >= 1000,
+                    _ => priority >= 0,
+                }
+            })
+            .max_by(|left, right| {
+                priority(left)
+                    .cmp(&priority(right))
+                    .then_with(|| left.version.cmp(&right.version))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::Available;
+    use super::Origin;
+    use super::Pattern;
+    use super::PinTarget;
+    use super::Policy;
+    use super::Preferences;
+    use super::ReleaseKey;
+
+    fn origin(archive: &str, site: &str) -> Origin {
+        Origin {
+            archive: Some(archive.to_string()),
+            origin: Some("Debian".to_string()),
+            component: Some("main".to_string()),
+            site: site.to_string(),
+            ..Origin::default()
+        }
+    }
+
+    fn available(version: &str, origins: &[Origin]) -> Available {
+        Available {
+            version: version.parse().unwrap(),
+            origins: origins.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let prefs = Preferences::parse(io::Cursor::new(
+            r#"
+# a comment
+Explanation: prefer stable
+Package: *
+Pin: release a=stable, o="Debian"
+Pin-Priority: 900
+
+Package: firefox* /^thunder/ curl
+Pin: origin "packages.mozilla.org"
+Pin-Priority: 1001
+
+Package: curl
+Pin: version 7.8*
+Pin-Priority: -1
+"#,
+        ))
+        .unwrap();
+
+        assert_eq!(3, prefs.pins.len());
+
+        let general = &prefs.pins[0];
+        assert!(general.is_general());
+        assert_eq!(900, general.priority);
+        match &general.target {
+            PinTarget::Release(conditions) => {
+                assert_eq!(ReleaseKey::Archive, conditions[0].0);
+                assert!(conditions[0].1.matches("stable"));
+                assert_eq!(ReleaseKey::Origin, conditions[1].0);
+                assert!(conditions[1].1.matches("Debian"));
+            }
+            other => panic!("unexpected target: {:?}", other),
+        }
+
+        let mozilla = &prefs.pins[1];
+        assert!(!mozilla.is_general());
+        for name in &["firefox", "firefox-esr", "thunderbird", "curl"] {
+            assert!(mozilla.matches_package(name), "{}", name);
+        }
+        for name in &["libcurl4", "iceweasel", "mythunderbird"] {
+            assert!(!mozilla.matches_package(name), "{}", name);
+        }
+
+        assert_eq!(-1, prefs.pins[2].priority);
+
+        assert!(Pattern::new("lib[!x]z?").unwrap().matches("libaz1"));
+        assert!(!Pattern::new("lib[!x]z?").unwrap().matches("libxz1"));
+        assert!(Preferences::parse(io::Cursor::new("Package: *\nPin: nonsense\n")).is_err());
+    }
+
+    #[test]
+    fn candidate() {
+        let stable = origin("stable", "deb.debian.org");
+        let unstable = origin("unstable", "deb.debian.org");
+
+        let versions = vec![
+            available("1.0", &[stable.clone(), Origin::installed()]),
+            available("2.0", &[unstable.clone()]),
+        ];
+
+        let policy = Policy::default();
+        assert_eq!(
+            500,
+            policy.priority("foo", &versions[0].version, &versions[0].origins)
+        );
+        assert_eq!(
+            "2.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+
+        let mut policy = Policy::new(
+            Preferences::parse(io::Cursor::new(
+                "Package: *\nPin: release a=unstable\nPin-Priority: 100\n",
+            ))
+            .unwrap(),
+        );
+        assert_eq!(
+            "1.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+
+        // the target release doesn't beat an explicit pin, but does beat the default
+        policy.set_target_release(Some("stable".to_string()));
+        assert_eq!(
+            990,
+            policy.priority("foo", &versions[0].version, &versions[0].origins)
+        );
+        assert_eq!(
+            100,
+            policy.priority("foo", &versions[1].version, &versions[1].origins)
+        );
+
+        // a higher priority, older version, isn't a downgrade unless it's at least 1000
+        let versions = vec![
+            available("1.0", &[stable.clone()]),
+            available("2.0", &[unstable, Origin::installed()]),
+        ];
+        let policy = Policy::new(
+            Preferences::parse(io::Cursor::new(
+                "Package: foo\nPin: release a=stable\nPin-Priority: 999\n",
+            ))
+            .unwrap(),
+        );
+        assert_eq!(
+            "2.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+        assert_eq!(
+            "2.0",
+            policy.candidate("bar", &versions).unwrap().version.as_str()
+        );
+
+        let policy = Policy::new(
+            Preferences::parse(io::Cursor::new(
+                "Package: foo\nPin: release a=stable\nPin-Priority: 1000\n",
+            ))
+            .unwrap(),
+        );
+        assert_eq!(
+            "1.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+
+        // experimental is never picked automatically; backports only for upgrades
+        let experimental = Origin {
+            not_automatic: true,
+            ..origin("experimental", "deb.debian.org")
+        };
+        let backports = Origin {
+            but_automatic_upgrades: true,
+            ..experimental.clone()
+        };
+        let policy = Policy::default();
+        let versions = vec![
+            available("1.0", &[stable.clone()]),
+            available("3.0", &[experimental]),
+        ];
+        assert_eq!(
+            1,
+            policy.priority("foo", &versions[1].version, &versions[1].origins)
+        );
+        assert_eq!(
+            "1.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+        let versions = vec![
+            available("1.0", &[stable.clone(), Origin::installed()]),
+            available("1.1", &[backports.clone()]),
+        ];
+        assert_eq!(
+            "1.0",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+        let versions = vec![
+            available("1.0", &[stable.clone()]),
+            available("1.1", &[backports.clone(), Origin::installed()]),
+            available("1.2", &[backports]),
+        ];
+        assert_eq!(
+            "1.2",
+            policy.candidate("foo", &versions).unwrap().version.as_str()
+        );
+
+        // negative priorities are never candidates
+        let versions = vec![available("1.0", &[stable])];
+        let policy = Policy::new(
+            Preferences::parse(io::Cursor::new(
+                "Package: foo\nPin: version 1.*\nPin-Priority: -1\n",
+            ))
+            .unwrap(),
+        );
+        assert!(policy.candidate("foo", &versions).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+//! Find which _Packages_ refer to a package, e.g. "who depends on `libfoo`?".
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::parse::Dependency;
+use crate::parse::Package;
+use crate::parse::PackageType;
+
+/// A relationship field in a binary or source _Package_.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Field {
+    PreDepends,
+    Depends,
+    Recommends,
+    Suggests,
+    Enhances,
+    Breaks,
+    Conflicts,
+    Replaces,
+    Provides,
+    BuildDepends,
+    BuildDependsArch,
+    BuildDependsIndep,
+    BuildConflicts,
+    BuildConflictsArch,
+    BuildConflictsIndep,
+}
+
+/// One mention of a package name, by another _Package_.
+#[derive(Copy, Clone, Debug)]
+pub struct Reference<'p> {
+    pub package: &'p Package,
+    pub field: Field,
+    /// The whole clause, including any alternatives.
+    pub dependency: &'p Dependency,
+}
+
+/// An index from package names (including virtual ones) to everything which mentions them.
+pub struct ReverseDependencies<'p> {
+    by_name: HashMap<&'p str, Vec<Reference<'p>>>,
+}
+
+impl Field {
+    /// Is this a source package's field?
+    pub fn is_build(&self) -> bool {
+        use self::Field::*;
+        matches!(
+            self,
+            BuildDepends
+                | BuildDependsArch
+                | BuildDependsIndep
+                | BuildConflicts
+                | BuildConflictsArch
+                | BuildConflictsIndep
+        )
+    }
+
+    /// The name of the field, as it appears in a _Listing_.
+    pub fn name(&self) -> &'static str {
+        use self::Field::*;
+        match self {
+            PreDepends => "Pre-Depends",
+            Depends => "Depends",
+            Recommends => "Recommends",
+            Suggests => "Suggests",
+            Enhances => "Enhances",
+            Breaks => "Breaks",
+            Conflicts => "Conflicts",
+            Replaces => "Replaces",
+            Provides => "Provides",
+            BuildDepends => "Build-Depends",
+            BuildDependsArch => "Build-Depends-Arch",
+            BuildDependsIndep => "Build-Depends-Indep",
+            BuildConflicts => "Build-Conflicts",
+            BuildConflictsArch => "Build-Conflicts-Arch",
+            BuildConflictsIndep => "Build-Conflicts-Indep",
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl<'p> ReverseDependencies<'p> {
+    /// Index the relationship fields of all these binary and source _Packages_.
+    pub fn new<I: IntoIterator<Item = &'p Package>>(packages: I) -> Self {
+        let mut by_name: HashMap<&'p str, Vec<Reference<'p>>> = HashMap::new();
+
+        for package in packages {
+            for (field, deps) in fields(package) {
+                for dependency in deps {
+                    let mut names: Vec<&str> = dependency
+                        .alternate
+                        .iter()
+                        .map(|alt| alt.package.as_str())
+                        .collect();
+                    // `foo (>= 1) | foo (<< 0.5)` is only one reference to `foo`
+                    names.sort();
+                    names.dedup();
+
+                    for name in names {
+                        by_name.entry(name).or_default().push(Reference {
+                            package,
+                            field,
+                            dependency,
+                        });
+                    }
+                }
+            }
+        }
+
+        ReverseDependencies { by_name }
+    }
+
+    /// Everything which mentions this name, in any field.
+    ///
+    /// For a virtual package, this includes the _Packages_ which `Provide` it.
+    pub fn of(&self, name: &str) -> &[Reference<'p>] {
+        self.by_name.get(name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// The binary _Packages_ which mention this name, ignoring build relationships.
+    pub fn binaries_of<'s>(&'s self, name: &str) -> impl Iterator<Item = &'s Reference<'p>> {
+        self.of(name).iter().filter(|r| !r.field.is_build())
+    }
+
+    /// The source _Packages_ which build-depend on (or build-conflict with) this name.
+    pub fn sources_of<'s>(&'s self, name: &str) -> impl Iterator<Item = &'s Reference<'p>> {
+        self.of(name).iter().filter(|r| r.field.is_build())
+    }
+}
+
+fn fields(package: &Package) -> Vec<(Field, &Vec<Dependency>)> {
+    use self::Field::*;
+    match &package.style {
+        PackageType::Binary(bin) => vec![
+            (PreDepends, &bin.pre_depends),
+            (Depends, &bin.depends),
+            (Recommends, &bin.recommends),
+            (Suggests, &bin.suggests),
+            (Enhances, &bin.enhances),
+            (Breaks, &bin.breaks),
+            (Conflicts, &bin.conflicts),
+            (Replaces, &bin.replaces),
+            (Provides, &bin.provides),
+        ],
+        PackageType::Source(src) => vec![
+            (BuildDepends, &src.build_dep),
+            (BuildDependsArch, &src.build_dep_arch),
+            (BuildDependsIndep, &src.build_dep_indep),
+            (BuildConflicts, &src.build_conflict),
+            (BuildConflictsArch, &src.build_conflict_arch),
+            (BuildConflictsIndep, &src.build_conflict_indep),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Field;
+    use super::ReverseDependencies;
+    use crate::parse::Package;
+
+    #[test]
+    fn rdeps() {
+        let packages = vec![
+            Package::parse_test("Package: app\nVersion: 1\nArchitecture: amd64\nDepends: libfoo1 (>= 1) | libfoo1-compat\nSuggests: libfoo1"),
+            Package::parse_test("Package: libfoo1\nVersion: 1\nArchitecture: amd64\nProvides: libfoo-abi"),
+            Package::parse_test("Package: plugin\nVersion: 1\nArchitecture: all\nDepends: libfoo-abi"),
+            Package::parse_test("Package: foo\nVersion: 1\nBinary: libfoo1\nArchitecture: any\nFormat: 3.0 (native)\nDirectory: pool/f\nFiles:\n 00000000000000000000000000000000 0 foo_1.dsc\nBuild-Depends: debhelper, libbar-dev [amd64]"),
+        ];
+        let index = ReverseDependencies::new(&packages);
+
+        let found: Vec<(&str, Field)> = index
+            .of("libfoo1")
+            .iter()
+            .map(|r| (r.package.name.as_str(), r.field))
+            .collect();
+        assert_eq!(
+            vec![("app", Field::Depends), ("app", Field::Suggests)],
+            found
+        );
+
+        let found: Vec<(&str, Field)> = index
+            .of("libfoo-abi")
+            .iter()
+            .map(|r| (r.package.name.as_str(), r.field))
+            .collect();
+        assert_eq!(
+            vec![("libfoo1", Field::Provides), ("plugin", Field::Depends)],
+            found
+        );
+
+        assert_eq!(0, index.binaries_of("libbar-dev").count());
+        assert_eq!(
+            "libbar-dev [amd64]",
+            index
+                .sources_of("libbar-dev")
+                .next()
+                .unwrap()
+                .dependency
+                .to_string()
+        );
+        assert!(index.of("unknown").is_empty());
+    }
+}
@@ -8,10 +8,12 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Error;
 use chrono::DateTime;
+use chrono::Duration;
 use chrono::Utc;
 use gpgrv::Keyring;
 use insideout::InsideOut;
@@ -24,8 +26,10 @@ use crate::fetch::fetch;
 use crate::fetch::Download;
 use crate::rfc822;
 use crate::rfc822::RfcMapExt;
+use crate::signing;
 use crate::signing::GpgClient;
 use crate::sources_list::Entry;
+use crate::sources_list::SignedBy;
 
 pub struct RequestedReleases {
     releases: Vec<(RequestedRelease, Vec<Entry>)>,
@@ -33,28 +37,44 @@ pub struct RequestedReleases {
 
 #[derive(Clone, PartialOrd, Ord, Hash, PartialEq, Eq, Debug)]
 pub struct RequestedRelease {
-    mirror: Url,
+    pub(crate) mirror: Url,
     /// This can also be called "suite" in some places,
     /// e.g. "unstable" (suite) == "sid" (codename)
     pub codename: String,
 
     pub arches: Vec<String>,
     pub untrusted: bool,
+    /// If present, only these keys may sign this release, instead of the system keyring.
+    pub signed_by: Option<SignedBy>,
+    pub check_valid_until: bool,
 }
 
+/// The contents of a _Release_ file, e.g. `dists/sid/InRelease`.
 #[derive(Debug, Clone)]
 pub struct ReleaseFile {
-    origin: String,
-    label: String,
-    suite: Option<String>,
-    codename: Option<String>,
-    changelogs: Option<String>,
-    date: DateTime<Utc>,
-    valid_until: Option<DateTime<Utc>>,
+    pub origin: String,
+    pub label: String,
+    pub suite: Option<String>,
+    pub codename: Option<String>,
+    /// e.g. `12.4`, for stable releases.
+    pub version: Option<String>,
+    /// A url template for the changelogs of packages in this release.
+    pub changelogs: Option<String>,
+    pub date: DateTime<Utc>,
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Packages from this release should not be installed unless explicitly requested,
+    /// e.g. `experimental`.
+    pub not_automatic: bool,
+    /// ..but installed packages from it should still be upgraded, e.g. `-backports`.
+    pub but_automatic_upgrades: bool,
     pub acquire_by_hash: bool,
     pub arches: Vec<String>,
-    components: Vec<String>,
-    description: Option<String>,
+    /// `Packages` if the `all` packages are only listed in the per-architecture _Listings_.
+    pub no_support_for_architecture_all: Option<String>,
+    pub components: Vec<String>,
+    pub description: Option<String>,
+    /// The fingerprints of the keys which may sign the next version of this release.
+    pub signed_by: Vec<String>,
     pub contents: Vec<ReleaseContent>,
 }
 
@@ -118,6 +138,50 @@ impl RequestedRelease {
             .as_ref()
             .join(format!("{}_Verified", self.filesystem_safe()))
     }
+
+    /// Where a newly verified release waits, until we've checked it's fresh enough to use.
+    pub fn pending_path<P: AsRef<Path>>(&self, lists_dir: P) -> PathBuf {
+        lists_dir
+            .as_ref()
+            .join(format!("{}_Pending", self.filesystem_safe()))
+    }
+}
+
+impl ReleaseFile {
+    /// Refuse a release which has expired at `now`, either according to its own `Valid-Until`,
+    /// or because its `Date` is more than `max_valid_time` ago.
+    ///
+    /// Like apt's `check-valid-until=no`, `check_valid_until` turns off both checks.
+    pub fn check_valid_at(
+        &self,
+        now: DateTime<Utc>,
+        check_valid_until: bool,
+        max_valid_time: Option<Duration>,
+    ) -> Result<(), Error> {
+        if !check_valid_until {
+            return Ok(());
+        }
+
+        if let Some(valid_until) = self.valid_until {
+            ensure!(
+                now <= valid_until,
+                "release expired at {} (it is now {})",
+                valid_until,
+                now
+            );
+        }
+
+        if let Some(max_valid_time) = max_valid_time {
+            ensure!(
+                now <= self.date + max_valid_time,
+                "release dated {} is older than the maximum allowed age, {}",
+                self.date,
+                max_valid_time
+            );
+        }
+
+        Ok(())
+    }
 }
 
 impl RequestedReleases {
@@ -142,6 +206,8 @@ impl RequestedReleases {
                 codename: entry.suite_codename.to_string(),
                 arches: arches.to_vec(),
                 untrusted: entry.untrusted,
+                signed_by: entry.signed_by.clone(),
+                check_valid_until: entry.check_valid_until,
             }) {
                 hash_map::Entry::Vacant(vacancy) => {
                     vacancy.insert(vec![entry.clone()]);
@@ -150,6 +216,19 @@ impl RequestedReleases {
             }
         }
 
+        let mut seen = HashMap::with_capacity(ret.len());
+        for release in ret.keys() {
+            if let Some(other) = seen.insert(release.filesystem_safe(), release) {
+                bail!(
+                    "conflicting options for {} {}: {:?} vs. {:?}",
+                    release.mirror,
+                    release.codename,
+                    release,
+                    other
+                );
+            }
+        }
+
         Ok(RequestedReleases {
             releases: ret.into_iter().collect(),
         })
@@ -160,10 +239,10 @@ impl RequestedReleases {
         lists_dir: P,
         keyring: &Keyring,
         client: &reqwest::Client,
+        max_valid_time: Option<Duration>,
     ) -> Result<(), Error> {
         let lists_dir = lists_dir.as_ref();
 
-        let mut gpg = GpgClient::new(keyring);
         let mut set = JoinSet::new();
 
         for &(ref release, _) in &self.releases {
@@ -179,9 +258,18 @@ impl RequestedReleases {
 
         while let Some(res) = set.join_next().await {
             let (res, release, dest) = res?;
-            let verified = release.verified_path(lists_dir);
+            let pending = release.pending_path(lists_dir);
+
+            let own_keyring = release
+                .signed_by
+                .as_ref()
+                .map(signing::load_keyring)
+                .inside_out()
+                .with_context(|| anyhow!("loading signed-by keys for {:?}", release))?;
+            let mut gpg = GpgClient::new(own_keyring.as_ref().unwrap_or(keyring));
+
             match res {
-                Ok(_) => gpg.read_clearsigned(&dest, &verified, !release.untrusted),
+                Ok(_) => gpg.read_clearsigned(&dest, &pending, !release.untrusted),
                 Err(_) => {
                     let mut detatched_signature = dest.as_os_str().to_os_string();
                     detatched_signature.push(".gpg");
@@ -204,17 +292,21 @@ impl RequestedReleases {
                             .await
                             .with_context(|| anyhow!("spawning thread"))?
                             .with_context(|| anyhow!("downloading Release file"))?;
-                        gpg.verify_detached(&dest, detatched_signature, verified)
+                        gpg.verify_detached(&dest, detatched_signature, &pending)
                     } else {
                         release_future
                             .await
                             .with_context(|| anyhow!("spawning thread"))?
                             .with_context(|| anyhow!("downloading Release file"))?;
+                        fs::copy(&dest, &pending)?;
                         Ok(())
                     }
                 }
             }
             .with_context(|| anyhow!("verifying {:?} at {:?}", release, dest))?;
+
+            accept_pending(&release, lists_dir, max_valid_time)
+                .with_context(|| anyhow!("checking freshness of {:?} at {:?}", release, dest))?;
         }
 
         Ok(())
@@ -234,6 +326,43 @@ impl RequestedReleases {
     }
 }
 
+/// Move a verified release into place, as long as it's in date, and isn't older
+/// than the one we already had (which would allow a mirror to replay old releases).
+fn accept_pending(
+    release: &RequestedRelease,
+    lists_dir: &Path,
+    max_valid_time: Option<Duration>,
+) -> Result<(), Error> {
+    let pending = release.pending_path(lists_dir);
+    let verified = release.verified_path(lists_dir);
+
+    let file = parse_release_file(&pending)?;
+
+    let checked = file
+        .check_valid_at(Utc::now(), release.check_valid_until, max_valid_time)
+        .and_then(|()| {
+            if !verified.exists() {
+                return Ok(());
+            }
+            let previous = parse_release_file(&verified)?;
+            ensure!(
+                file.date >= previous.date,
+                "refusing release dated {}, as we already have one from {}",
+                file.date,
+                previous.date
+            );
+            Ok(())
+        });
+
+    if let Err(e) = checked {
+        fs::remove_file(&pending).with_context(|| anyhow!("removing rejected {:?}", pending))?;
+        return Err(e);
+    }
+
+    fs::rename(&pending, &verified).with_context(|| anyhow!("storing {:?}", verified))?;
+    Ok(())
+}
+
 pub fn parse_release_file<P: AsRef<Path>>(path: P) -> Result<ReleaseFile, Error> {
     let mut file = String::with_capacity(100 * 1024);
     io::BufReader::new(
@@ -248,13 +377,15 @@ pub fn parse_release_file<P: AsRef<Path>>(path: P) -> Result<ReleaseFile, Error>
     )
 }
 
-fn parse_release(release: &str) -> Result<ReleaseFile, Error> {
+/// Parse the contents of a _Release_ file, once any signature has been removed.
+pub fn parse_release(release: &str) -> Result<ReleaseFile, Error> {
     let mut data = rfc822::fields_in_block(release).collect_to_map()?;
     Ok(ReleaseFile {
         origin: data.remove_value("Origin").one_line_req()?.to_string(),
         label: data.remove_value("Label").one_line_req()?.to_string(),
         suite: data.remove_value("Suite").one_line_owned()?,
         codename: data.remove_value("Codename").one_line_owned()?,
+        version: data.remove_value("Version").one_line_owned()?,
         changelogs: data.remove_value("Changelogs").one_line_owned()?,
         date: rfc822::parse_date(&data.remove_value("Date").one_line_req()?)?,
         valid_until: data
@@ -262,18 +393,42 @@ fn parse_release(release: &str) -> Result<ReleaseFile, Error> {
             .one_line()?
             .map(|s| rfc822::parse_date(&s))
             .inside_out()?,
+        not_automatic: yes_no(data.remove_value("NotAutomatic").one_line()?),
+        but_automatic_upgrades: yes_no(data.remove_value("ButAutomaticUpgrades").one_line()?),
         acquire_by_hash: data
             .remove_value("Acquire-By-Hash")
             .one_line()?
             .map(|s| "yes" == s)
             .unwrap_or(false),
         arches: data.remove_value("Architectures").split_whitespace()?,
+        no_support_for_architecture_all: data
+            .remove_value("No-Support-for-Architecture-all")
+            .one_line_owned()?,
         components: data.remove_value("Components").split_whitespace()?,
         description: data.remove_value("Description").one_line_owned()?,
+        signed_by: data
+            .remove_value("Signed-By")
+            .one_line()?
+            .map(|keys| {
+                keys.split(|c: char| ',' == c || c.is_whitespace())
+                    .filter(|key| !key.is_empty())
+                    .map(|key| key.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
         contents: load_contents(&mut data)?,
     })
 }
 
+/// Like apt's `StringToBool`: `yes`, `true` or `1`, in any case, and anything else is false.
+fn yes_no(value: Option<&str>) -> bool {
+    value.is_some_and(|value| {
+        ["yes", "true", "1"]
+            .iter()
+            .any(|yes| yes.eq_ignore_ascii_case(value))
+    })
+}
+
 fn load_contents(data: &mut HashMap<&str, Vec<&str>>) -> Result<Vec<ReleaseContent>, Error> {
     let md5s = take_checksums(data, "MD5Sum")?;
     let sha256s = take_checksums(data, "SHA256")?
@@ -324,3 +479,113 @@ fn parse_checksums<'s>(lines: &[&'s str]) -> Result<HashMap<(&'s str, u64), &'s
 
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::Duration;
+
+    use super::accept_pending;
+    use super::parse_release;
+    use super::RequestedRelease;
+    use crate::rfc822::parse_date;
+
+    const RELEASE: &str = "Origin: Debian
+Label: Debian
+Suite: unstable
+Codename: sid
+Date: Wed, 06 Feb 2019 14:29:43 UTC
+Valid-Until: Wed, 13 Feb 2019 14:29:43 UTC
+Architectures: amd64
+Components: main
+SHA256:
+ c3a1781dc47ba30d2c29eafd556d36917bb180c1f55c4862fedd48da28a2042f 1234 main/source/Sources
+";
+
+    #[test]
+    fn valid_until() {
+        let file = parse_release(RELEASE).unwrap();
+        let before = parse_date("Sun, 10 Feb 2019 00:00:00 UTC").unwrap();
+        let after = parse_date("Fri, 15 Feb 2019 00:00:00 UTC").unwrap();
+
+        file.check_valid_at(before, true, None).unwrap();
+        assert!(file.check_valid_at(after, true, None).is_err());
+        file.check_valid_at(after, false, None).unwrap();
+    }
+
+    #[test]
+    fn metadata() {
+        let file = parse_release(&format!(
+            "{}NotAutomatic: yes\nButAutomaticUpgrades: yes\nVersion: 12.4\n{}\n{}\n",
+            RELEASE,
+            "No-Support-for-Architecture-all: Packages",
+            "Signed-By: 4D64FEC119C2029067D6E791F8D2585B8783D481, AC530D520F2F3269F5E98313A48449044AAD5C5D"
+        ))
+        .unwrap();
+
+        assert_eq!("Debian", file.origin);
+        assert_eq!(Some("sid"), file.codename.as_deref());
+        assert_eq!(Some("12.4"), file.version.as_deref());
+        assert!(file.not_automatic);
+        assert!(file.but_automatic_upgrades);
+        assert_eq!(
+            Some("Packages"),
+            file.no_support_for_architecture_all.as_deref()
+        );
+        assert_eq!(2, file.signed_by.len());
+        assert_eq!(vec!["main".to_string()], file.components);
+
+        let file = parse_release(RELEASE).unwrap();
+        assert!(!file.not_automatic);
+        assert!(file.signed_by.is_empty());
+        let file = parse_release(&format!(
+            "{}NotAutomatic: True\nButAutomaticUpgrades: maybe\n",
+            RELEASE
+        ))
+        .unwrap();
+        assert!(file.not_automatic);
+        assert!(!file.but_automatic_upgrades);
+    }
+
+    #[test]
+    fn max_valid_time() {
+        let file = parse_release(RELEASE).unwrap();
+        let before = parse_date("Sun, 10 Feb 2019 00:00:00 UTC").unwrap();
+
+        file.check_valid_at(before, true, Some(Duration::days(7)))
+            .unwrap();
+        assert!(file
+            .check_valid_at(before, true, Some(Duration::days(1)))
+            .is_err());
+        file.check_valid_at(before, false, Some(Duration::days(1)))
+            .unwrap();
+    }
+
+    #[test]
+    fn replayed() {
+        let lists_dir = tempfile::tempdir().unwrap();
+        let release = RequestedRelease {
+            mirror: "http://deb.debian.org/debian/".parse().unwrap(),
+            codename: "sid".to_string(),
+            arches: vec!["amd64".to_string()],
+            untrusted: false,
+            signed_by: None,
+            check_valid_until: false,
+        };
+        let newer = RELEASE.replace("Wed, 06 Feb 2019", "Thu, 07 Feb 2019");
+
+        fs::write(release.verified_path(lists_dir.path()), &newer).unwrap();
+        fs::write(release.pending_path(lists_dir.path()), RELEASE).unwrap();
+        assert!(accept_pending(&release, lists_dir.path(), None).is_err());
+        assert!(!release.pending_path(lists_dir.path()).exists());
+        assert_eq!(
+            newer,
+            fs::read_to_string(release.verified_path(lists_dir.path())).unwrap()
+        );
+
+        fs::write(release.pending_path(lists_dir.path()), &newer).unwrap();
+        accept_pending(&release, lists_dir.path(), None).unwrap();
+        assert!(!release.pending_path(lists_dir.path()).exists());
+    }
+}
@@ -0,0 +1,768 @@
+//! Work out which _Packages_ need to be installed to satisfy some _Dependencies_.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), anyhow::Error> {
+//! # use fapt::system::System;
+//! use fapt::commands;
+//! use fapt::resolve::Resolver;
+//!
+//! let fapt = System::cache_only()?;
+//! let packages = commands::all_blocks(&fapt)?
+//!     .map(|block| block?.as_pkg())
+//!     .collect::<Result<Vec<_>, _>>()?;
+//!
+//! let resolver = Resolver::new(&packages, "amd64")?;
+//! for package in resolver.install(&["ripgrep"])? {
+//!     println!("{} {}", package.name, package.version);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::Error;
+
+use crate::parse::Arch;
+use crate::parse::Binary;
+use crate::parse::ConstraintOperator;
+use crate::parse::Dependency;
+use crate::parse::MultiArch;
+use crate::parse::Package;
+use crate::parse::SingleDependency;
+
+/// How many candidates we'll try before declaring the problem too hard.
+const MAX_STEPS: usize = 100_000;
+
+/// An index over a set of binary _Packages_ for one architecture.
+pub struct Resolver<'p> {
+    arch: Arch,
+    /// Where any building happens, which `:native` dependencies are for; usually `arch`.
+    build: Arch,
+    packages: Vec<(&'p Package, &'p Binary)>,
+    by_name: HashMap<&'p str, Vec<usize>>,
+    /// For each virtual package, who provides it.
+    providers: HashMap<&'p str, Vec<usize>>,
+}
+
+/// Why no install set could be found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Nothing with the name in the dependency is available.
+    ///
+    /// `needed_by` is the package that had the dependency, or `None` if it was requested directly.
+    Missing {
+        needed_by: Option<String>,
+        dependency: Dependency,
+    },
+    /// Something with the name is available, but not at a version the dependency allows.
+    WrongVersion {
+        needed_by: Option<String>,
+        dependency: Dependency,
+    },
+    /// `package` was needed, but can't be installed alongside `with`.
+    Conflict { package: String, with: String },
+    /// `package` was needed, but matches something which must not be installed.
+    Forbidden {
+        package: String,
+        dependency: Dependency,
+    },
+    /// The search was abandoned after trying too many combinations.
+    GaveUp,
+}
+
+#[derive(Default)]
+struct State<'p> {
+    chosen: Vec<usize>,
+    /// By name and architecture (`None` for `all`), as `Multi-Arch: same` packages
+    /// can be installed for more than one.
+    by_name: HashMap<(&'p str, Option<&'p Arch>), usize>,
+}
+
+#[derive(Copy, Clone)]
+struct Need<'d> {
+    needed_by: Option<usize>,
+    dependency: &'d Dependency,
+    /// How far down the chain of dependencies this is, from what was asked for.
+    depth: usize,
+}
+
+/// A `Problem`, and the depth of the need it was found at, so the shallowest,
+/// i.e. most direct, explanation can be reported.
+struct Failure {
+    depth: usize,
+    problem: Problem,
+}
+
+impl<'p> Resolver<'p> {
+    /// Index the binary _Packages_ which can be installed on `arch`, e.g. `amd64`.
+    ///
+    /// Source _Packages_, and those for other architectures, are ignored.
+    pub fn new<I: IntoIterator<Item = &'p Package>>(
+        packages: I,
+        arch: &str,
+    ) -> Result<Self, Error> {
+        Resolver::cross(packages, arch, arch)
+    }
+
+    /// Index the binary _Packages_ for cross-building on `build`, e.g. `amd64`,
+    /// for `host`, e.g. `armhf`.
+    ///
+    /// Both architectures' _Packages_ are indexed. Dependencies are satisfied on the host
+    /// architecture, unless they're qualified (e.g. `gcc:native`), or are for a package
+    /// from the build architecture, or are met by a `Multi-Arch: foreign` _Package_.
+    pub fn cross<I: IntoIterator<Item = &'p Package>>(
+        packages: I,
+        build: &str,
+        host: &str,
+    ) -> Result<Self, Error> {
+        let arch: Arch = host.parse()?;
+        let build: Arch = build.parse()?;
+
+        let mut resolver = Resolver {
+            arch,
+            build,
+            packages: Vec::new(),
+            by_name: HashMap::new(),
+            providers: HashMap::new(),
+        };
+
+        for package in packages {
+            let bin = match package.as_bin() {
+                Some(bin) => bin,
+                None => continue,
+            };
+
+            if !package
+                .arches
+                .iter()
+                .any(|a| Arch::All == *a || a.matches(&resolver.arch) || a.matches(&resolver.build))
+            {
+                continue;
+            }
+
+            let idx = resolver.packages.len();
+            resolver.packages.push((package, bin));
+            resolver
+                .by_name
+                .entry(package.name.as_str())
+                .or_default()
+                .push(idx);
+
+            for provide in bin.provides.iter().flat_map(|d| &d.alternate) {
+                resolver
+                    .providers
+                    .entry(provide.package.as_str())
+                    .or_default()
+                    .push(idx);
+            }
+        }
+
+        for candidates in resolver.by_name.values_mut() {
+            let packages = &resolver.packages;
+            candidates.sort_by(|&l, &r| packages[r].0.version.cmp(&packages[l].0.version));
+        }
+
+        Ok(resolver)
+    }
+
+    /// The (host) architecture the _Packages_ were selected for.
+    pub fn arch(&self) -> &Arch {
+        &self.arch
+    }
+
+    /// The architecture `:native` dependencies are for, which is `arch()` unless cross-building.
+    pub fn build_arch(&self) -> &Arch {
+        &self.build
+    }
+
+    /// Find an install set for these package names, at any version.
+    pub fn install<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<&'p Package>, Problem> {
+        let wanted: Vec<Dependency> = names
+            .iter()
+            .map(|name| Dependency {
+                alternate: vec![SingleDependency {
+                    package: name.as_ref().to_string(),
+                    ..Default::default()
+                }],
+            })
+            .collect();
+        self.resolve(&wanted)
+    }
+
+    /// Find a set of _Packages_ which satisfies all of `wanted`, along with all of their
+    /// `Depends` and `Pre-Depends`, without violating any `Conflicts` or `Breaks`.
+    ///
+    /// Newer versions, and earlier alternatives, are preferred.
+    pub fn resolve(&self, wanted: &[Dependency]) -> Result<Vec<&'p Package>, Problem> {
+        self.resolve_avoiding(wanted, &[])
+    }
+
+    /// Like `resolve`, but additionally refuse to install anything matching `avoid`,
+    /// e.g. a source package's `Build-Conflicts`.
+    pub fn resolve_avoiding(
+        &self,
+        wanted: &[Dependency],
+        avoid: &[Dependency],
+    ) -> Result<Vec<&'p Package>, Problem> {
+        let mut pending = wanted
+            .iter()
+            .rev()
+            .map(|dependency| Need {
+                needed_by: None,
+                dependency,
+                depth: 0,
+            })
+            .collect();
+
+        let mut state = State::default();
+        self.search(&mut state, &mut pending, avoid, &mut 0)
+            .map_err(|failure| failure.problem)?;
+        Ok(state
+            .chosen
+            .into_iter()
+            .map(|idx| self.packages[idx].0)
+            .collect())
+    }
+
+    /// Check whether each of the indexed _Packages_ could be installed on its own,
+    /// like `dose-debcheck`. Returns the ones which can't, and why.
+    pub fn uninstallable(&self) -> Vec<(&'p Package, Problem)> {
+        (0..self.packages.len())
+            .filter_map(|idx| {
+                self.installable(idx)
+                    .err()
+                    .map(|problem| (self.packages[idx].0, problem))
+            })
+            .collect()
+    }
+
+    fn installable(&self, idx: usize) -> Result<(), Problem> {
+        let mut state = State::default();
+        let mut pending = Vec::new();
+        self.add(&mut state, idx, &[])?;
+        self.push_depends(&mut pending, idx, 1);
+        self.search(&mut state, &mut pending, &[], &mut 0)
+            .map_err(|failure| failure.problem)
+    }
+
+    fn push_depends(&self, pending: &mut Vec<Need<'p>>, idx: usize, depth: usize) {
+        let (_, bin) = self.packages[idx];
+        for dependency in bin.depends.iter().chain(&bin.pre_depends).rev() {
+            pending.push(Need {
+                needed_by: Some(idx),
+                dependency,
+                depth,
+            });
+        }
+    }
+
+    /// Satisfy everything in `pending`, adding to `state`.
+    ///
+    /// On failure, `state` and `pending` are left as they were.
+    fn search(
+        &self,
+        state: &mut State<'p>,
+        pending: &mut Vec<Need<'p>>,
+        avoid: &[Dependency],
+        steps: &mut usize,
+    ) -> Result<(), Failure> {
+        let mut satisfied = Vec::new();
+        let result = self.search_next(state, pending, &mut satisfied, avoid, steps);
+        if result.is_err() {
+            pending.extend(satisfied.into_iter().rev());
+        }
+        result
+    }
+
+    fn search_next(
+        &self,
+        state: &mut State<'p>,
+        pending: &mut Vec<Need<'p>>,
+        satisfied: &mut Vec<Need<'p>>,
+        avoid: &[Dependency],
+        steps: &mut usize,
+    ) -> Result<(), Failure> {
+        let need = loop {
+            match pending.pop() {
+                None => return Ok(()),
+                Some(need)
+                    if need
+                        .dependency
+                        .alternate
+                        .iter()
+                        .any(|alt| self.satisfied(state, need.needed_by, alt)) =>
+                {
+                    satisfied.push(need)
+                }
+                Some(need) => break need,
+            }
+        };
+
+        let mut first_problem: Option<Failure> = None;
+        let mut found = |failure: Failure| match &first_problem {
+            Some(first) if first.depth <= failure.depth => (),
+            _ => first_problem = Some(failure),
+        };
+
+        for idx in self.candidates(&need) {
+            *steps += 1;
+            if *steps > MAX_STEPS {
+                pending.push(need);
+                return Err(Failure {
+                    depth: need.depth,
+                    problem: Problem::GaveUp,
+                });
+            }
+
+            if let Err(problem) = self.add(state, idx, avoid) {
+                found(Failure {
+                    depth: need.depth,
+                    problem,
+                });
+                continue;
+            }
+
+            let mark = pending.len();
+            self.push_depends(pending, idx, need.depth + 1);
+
+            match self.search(state, pending, avoid, steps) {
+                Ok(()) => return Ok(()),
+                Err(failure) => {
+                    pending.truncate(mark);
+                    self.remove(state, idx);
+                    if Problem::GaveUp == failure.problem {
+                        pending.push(need);
+                        return Err(failure);
+                    }
+                    found(failure);
+                }
+            }
+        }
+
+        let failure = first_problem.unwrap_or_else(|| Failure {
+            depth: need.depth,
+            problem: self.unsatisfiable(&need),
+        });
+        pending.push(need);
+        Err(failure)
+    }
+
+    /// Explain why there are no candidates at all for a dependency.
+    fn unsatisfiable(&self, need: &Need) -> Problem {
+        let needed_by = need.needed_by.map(|idx| self.label(idx));
+        let dependency = need.dependency.clone();
+        let known = need.dependency.alternate.iter().any(|alt| {
+            self.by_name.contains_key(alt.package.as_str())
+                || self.providers.contains_key(alt.package.as_str())
+        });
+
+        if known {
+            Problem::WrongVersion {
+                needed_by,
+                dependency,
+            }
+        } else {
+            Problem::Missing {
+                needed_by,
+                dependency,
+            }
+        }
+    }
+
+    /// Everything which could satisfy a dependency, in order of preference.
+    fn candidates(&self, need: &Need) -> Vec<usize> {
+        let mut ret = Vec::new();
+        for alt in &need.dependency.alternate {
+            let real = self.by_name.get(alt.package.as_str()).into_iter().flatten();
+            let virt = self
+                .providers
+                .get(alt.package.as_str())
+                .into_iter()
+                .flatten();
+            for &idx in real.chain(virt) {
+                if !ret.contains(&idx)
+                    && self.matches(alt, idx)
+                    && self.arch_satisfies(need.needed_by, alt, idx)
+                {
+                    ret.push(idx);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Is this dependency (of `needed_by`) met by something we've already chosen?
+    fn satisfied(&self, state: &State, needed_by: Option<usize>, alt: &SingleDependency) -> bool {
+        for arch in [Some(&self.arch), Some(&self.build), None] {
+            if let Some(&idx) = state.by_name.get(&(alt.package.as_str(), arch)) {
+                if self.matches(alt, idx) && self.arch_satisfies(needed_by, alt, idx) {
+                    return true;
+                }
+            }
+        }
+
+        self.providers
+            .get(alt.package.as_str())
+            .into_iter()
+            .flatten()
+            .any(|idx| {
+                Some(idx) == state.by_name.get(&self.key(*idx))
+                    && self.matches(alt, *idx)
+                    && self.arch_satisfies(needed_by, alt, *idx)
+            })
+    }
+
+    /// Is the package `idx` from an architecture which `alt`, a dependency of `needed_by`,
+    /// may be satisfied on?
+    ///
+    /// Unqualified dependencies are for the architecture of the package which has them
+    /// (the build architecture, for `all` packages), or the host architecture if they
+    /// were requested directly. `:any` dependencies need a `Multi-Arch: allowed` package.
+    fn arch_satisfies(&self, needed_by: Option<usize>, alt: &SingleDependency, idx: usize) -> bool {
+        let (_, bin) = self.packages[idx];
+        if alt.arch.as_ref().is_some_and(|arch| arch.is_any()) {
+            return MultiArch::Allowed == bin.multi_arch;
+        }
+
+        let arch = match self.arch_of(idx) {
+            Some(arch) => arch,
+            None => return true,
+        };
+
+        match &alt.arch {
+            Some(Arch::Native) => self.build == *arch,
+            Some(wanted) => wanted.matches(arch),
+            None => {
+                let wanted = match needed_by {
+                    Some(by) => self.arch_of(by).unwrap_or(&self.build),
+                    None => &self.arch,
+                };
+                wanted == arch || MultiArch::Foreign == bin.multi_arch
+            }
+        }
+    }
+
+    /// The architecture of the package `idx`, or `None` if it's `all`.
+    fn arch_of(&self, idx: usize) -> Option<&'p Arch> {
+        let (package, _) = self.packages[idx];
+        package.arches.iter().find(|a| Arch::All != **a)
+    }
+
+    fn key(&self, idx: usize) -> (&'p str, Option<&'p Arch>) {
+        let (package, _) = self.packages[idx];
+        (package.name.as_str(), self.arch_of(idx))
+    }
+
+    /// Can `idx` and `other`, which have the same name, be installed together?
+    ///
+    /// Only `Multi-Arch: same` packages can, for different architectures, at the same version.
+    fn coinstallable(&self, idx: usize, other: usize) -> bool {
+        let (package, bin) = self.packages[idx];
+        let (other_package, other_bin) = self.packages[other];
+        MultiArch::Same == bin.multi_arch
+            && MultiArch::Same == other_bin.multi_arch
+            && package.version == other_package.version
+            && self.arch_of(idx) != self.arch_of(other)
+    }
+
+    /// Does the package `idx` satisfy (or, for a conflict, hit) this single dependency?
+    fn matches(&self, alt: &SingleDependency, idx: usize) -> bool {
+        let (package, bin) = self.packages[idx];
+
+        if package.name == alt.package
+            && alt
+                .version_constraints
+                .iter()
+                .all(|c| c.satisfied_by(&package.version))
+        {
+            return true;
+        }
+
+        bin.provides
+            .iter()
+            .flat_map(|d| &d.alternate)
+            .filter(|provide| provide.package == alt.package)
+            .any(|provide| {
+                // an unversioned Provides can't satisfy a versioned relationship
+                let version = provide
+                    .version_constraints
+                    .iter()
+                    .find(|c| ConstraintOperator::Eq == c.operator);
+                match version {
+                    Some(version) => alt
+                        .version_constraints
+                        .iter()
+                        .all(|c| c.satisfied_by(&version.version)),
+                    None => alt.version_constraints.is_empty(),
+                }
+            })
+    }
+
+    fn add(&self, state: &mut State<'p>, idx: usize, avoid: &[Dependency]) -> Result<(), Problem> {
+        let (package, _) = self.packages[idx];
+
+        for dependency in avoid {
+            if dependency
+                .alternate
+                .iter()
+                .any(|alt| self.matches(alt, idx))
+            {
+                return Err(Problem::Forbidden {
+                    package: self.label(idx),
+                    dependency: dependency.clone(),
+                });
+            }
+        }
+
+        for &other in &state.chosen {
+            let (other_package, _) = self.packages[other];
+            if (package.name == other_package.name && !self.coinstallable(idx, other))
+                || self.conflicts(idx, other)
+                || self.conflicts(other, idx)
+            {
+                return Err(self.conflict(idx, other));
+            }
+        }
+
+        state.chosen.push(idx);
+        state.by_name.insert(self.key(idx), idx);
+        Ok(())
+    }
+
+    fn remove(&self, state: &mut State<'p>, idx: usize) {
+        assert_eq!(Some(idx), state.chosen.pop());
+        state.by_name.remove(&self.key(idx));
+    }
+
+    /// Does `idx` declare that it can't be installed with `other`?
+    fn conflicts(&self, idx: usize, other: usize) -> bool {
+        let (_, bin) = self.packages[idx];
+        // packages may conflict with things they provide, to stop other providers
+        // from being installed, but they never conflict with themselves
+        bin.conflicts
+            .iter()
+            .chain(&bin.breaks)
+            .flat_map(|d| &d.alternate)
+            .any(|alt| self.matches(alt, other))
+    }
+
+    fn conflict(&self, idx: usize, other: usize) -> Problem {
+        Problem::Conflict {
+            package: self.label(idx),
+            with: self.label(other),
+        }
+    }
+
+    fn label(&self, idx: usize) -> String {
+        let (package, _) = self.packages[idx];
+        format!("{} {}", package.name, package.version)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing {
+                needed_by,
+                dependency,
+            } => {
+                wanted_by(f, needed_by, dependency)?;
+                write!(f, "which is not available")
+            }
+            Problem::WrongVersion {
+                needed_by,
+                dependency,
+            } => {
+                wanted_by(f, needed_by, dependency)?;
+                write!(f, "but no available version satisfies it")
+            }
+            Problem::Conflict { package, with } => {
+                write!(f, "{} can't be installed alongside {}", package, with)
+            }
+            Problem::Forbidden {
+                package,
+                dependency,
+            } => write!(
+                f,
+                "{} is needed, but conflicts with {}",
+                package, dependency
+            ),
+            Problem::GaveUp => write!(f, "gave up after trying {} candidates", MAX_STEPS),
+        }
+    }
+}
+
+impl Problem {
+    /// A short, stable name for this kind of problem, e.g. for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::Missing { .. } => "missing",
+            Problem::WrongVersion { .. } => "wrong-version",
+            Problem::Conflict { .. } => "conflict",
+            Problem::Forbidden { .. } => "forbidden",
+            Problem::GaveUp => "gave-up",
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
+fn wanted_by(
+    f: &mut fmt::Formatter,
+    needed_by: &Option<String>,
+    dependency: &Dependency,
+) -> fmt::Result {
+    match needed_by {
+        Some(needed_by) => write!(f, "{} depends on {}, ", needed_by, dependency),
+        None => write!(f, "{} is wanted, ", dependency),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Problem;
+    use super::Resolver;
+    use crate::parse::Package;
+
+    fn pkgs(blocks: &[&str]) -> Vec<Package> {
+        blocks
+            .iter()
+            .map(|block| Package::parse_test(block))
+            .collect()
+    }
+
+    fn names(packages: Vec<&Package>) -> Vec<String> {
+        let mut ret: Vec<String> = packages
+            .into_iter()
+            .map(|p| format!("{}={}", p.name, p.version))
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn depends_and_alternatives() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: b (>= 2), c | d",
+            "Package: b\nVersion: 1\nArchitecture: amd64",
+            "Package: b\nVersion: 2\nArchitecture: all\nPre-Depends: d",
+            "Package: c\nVersion: 1\nArchitecture: i386",
+            "Package: d\nVersion: 1\nArchitecture: amd64",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        assert_eq!(
+            vec!["a=1", "b=2", "d=1"],
+            names(resolver.install(&["a"]).unwrap())
+        );
+    }
+
+    #[test]
+    fn versioned_provides() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: v (>= 2)",
+            "Package: old\nVersion: 5\nArchitecture: amd64\nProvides: v",
+            "Package: new\nVersion: 1\nArchitecture: amd64\nProvides: v (= 3)",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        assert_eq!(
+            vec!["a=1", "new=1"],
+            names(resolver.install(&["a"]).unwrap())
+        );
+    }
+
+    #[test]
+    fn backtrack_over_conflicts() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: x | y, z",
+            "Package: x\nVersion: 1\nArchitecture: amd64",
+            "Package: y\nVersion: 1\nArchitecture: amd64",
+            "Package: z\nVersion: 1\nArchitecture: amd64\nBreaks: x",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        assert_eq!(
+            vec!["a=1", "y=1", "z=1"],
+            names(resolver.install(&["a"]).unwrap())
+        );
+    }
+
+    #[test]
+    fn explain() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: b, c (>> 1)",
+            "Package: b\nVersion: 1\nArchitecture: amd64\nConflicts: d",
+            "Package: c\nVersion: 1\nArchitecture: amd64",
+            "Package: d\nVersion: 1\nArchitecture: amd64",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        assert_eq!(
+            "a 1 depends on c (>> 1), but no available version satisfies it",
+            resolver.install(&["a"]).unwrap_err().to_string()
+        );
+        assert_eq!(
+            Problem::Conflict {
+                package: "d 1".to_string(),
+                with: "b 1".to_string(),
+            },
+            resolver.install(&["b", "d"]).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn multi_arch_any() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: perl:any",
+            "Package: b\nVersion: 1\nArchitecture: amd64\nDepends: python3:any",
+            "Package: perl\nVersion: 1\nArchitecture: amd64\nMulti-Arch: allowed",
+            "Package: python3\nVersion: 1\nArchitecture: amd64",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        assert_eq!(
+            vec!["a=1", "perl=1"],
+            names(resolver.install(&["a"]).unwrap())
+        );
+        // only `Multi-Arch: allowed` packages can satisfy `:any`
+        assert!(resolver.install(&["b"]).is_err());
+    }
+
+    #[test]
+    fn explain_shallowest() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 2\nArchitecture: amd64\nDepends: b",
+            "Package: a\nVersion: 1\nArchitecture: amd64",
+            "Package: b\nVersion: 1\nArchitecture: amd64\nConflicts: a (>= 2)",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        // trying a=2 hits the conflict first, but nothing could satisfy `missing`
+        assert_eq!(
+            "missing is wanted, which is not available",
+            resolver.install(&["a", "missing"]).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn uninstallable() {
+        let packages = pkgs(&[
+            "Package: a\nVersion: 1\nArchitecture: amd64\nDepends: b",
+            "Package: b\nVersion: 1\nArchitecture: amd64\nDepends: gone",
+            "Package: c\nVersion: 1\nArchitecture: amd64\nDepends: d | a",
+            "Package: d\nVersion: 1\nArchitecture: amd64\nConflicts: c",
+            "Package: e\nVersion: 1\nArchitecture: amd64\nDepends: b | d",
+        ]);
+        let resolver = Resolver::new(&packages, "amd64").unwrap();
+        let report: Vec<(String, &str)> = resolver
+            .uninstallable()
+            .into_iter()
+            .map(|(p, problem)| (p.name.to_string(), problem.kind()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a".to_string(), "missing"),
+                ("b".to_string(), "missing"),
+                ("c".to_string(), "conflict"),
+            ],
+            report
+        );
+    }
+}
@@ -158,6 +158,44 @@ impl<R: Read> Iterator for Blocks<R> {
     }
 }
 
+/// Split a hand-written file, e.g. `debian/control` or a `.sources`, into _Blocks_,
+/// separated by blank lines, dropping `#` comments.
+///
+/// Each _Block_ comes with the (zero-based) line it started on, for error reporting.
+pub fn commented_blocks<R: BufRead>(from: R) -> Result<Vec<(usize, String)>, Error> {
+    let mut blocks = Vec::new();
+    let mut block = String::new();
+    let mut block_start = 0;
+
+    for (no, line) in from.lines().enumerate() {
+        let line = line.with_context(|| anyhow!("reading around line {}", no))?;
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        if !line.trim().is_empty() {
+            if block.is_empty() {
+                block_start = no;
+            }
+            block.push_str(&line);
+            block.push('\n');
+            continue;
+        }
+
+        if !block.is_empty() {
+            blocks.push((block_start, block.clone()));
+            block.clear();
+        }
+    }
+
+    if !block.is_empty() {
+        blocks.push((block_start, block));
+    }
+
+    Ok(blocks)
+}
+
 fn one_line<'a>(lines: &[&'a str]) -> Result<&'a str, Error> {
     ensure!(1 == lines.len(), "{:?} isn't exactly one line", lines);
     Ok(lines[0])
@@ -196,6 +234,13 @@ impl<'s> RfcMapExt for HashMap<&'s str, Vec<&'s str>> {
     }
 }
 
+/// Copy whatever's left in a `Map`, e.g. the fields which weren't parsed.
+pub fn to_owned_map(map: &Map) -> HashMap<String, Vec<String>> {
+    map.iter()
+        .map(|(k, v)| (k.to_string(), v.iter().map(|v| v.to_string()).collect()))
+        .collect()
+}
+
 /// An optional list-of-lines from a `Map`, which carries
 /// the original _Key_ along, for error reporting purposes.
 pub struct Value<'k, T> {
@@ -270,6 +315,7 @@ impl<'k, 's, T: AsRef<[&'s str]>> Value<'k, T> {
 mod tests {
     use anyhow::Error;
 
+    use super::commented_blocks;
     use super::fields_in_block;
     use super::parse_date;
     use super::Field;
@@ -358,4 +404,19 @@ mod tests {
             ),
         );
     }
+
+    #[test]
+    fn commented() {
+        let blocks = commented_blocks(
+            &b"# leading comment\n\nFoo: bar\n# inner\nBaz: quux\n\n\n\nFoo: two\n"[..],
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                (2, "Foo: bar\nBaz: quux\n".to_string()),
+                (8, "Foo: two\n".to_string()),
+            ],
+            blocks
+        );
+    }
 }
@@ -1,13 +1,21 @@
+use std::ffi::OsStr;
 use std::fs;
 use std::io;
+use std::io::BufRead;
+use std::io::Write;
 use std::path::Path;
 
 use anyhow::anyhow;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Error;
 use gpgrv::Keyring;
 use tempfile_fast::PersistableTempFile;
 
+use crate::sources_list::SignedBy;
+
+const BEGIN_SIGNED_MESSAGE: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+
 pub struct GpgClient<'k> {
     keyring: &'k Keyring,
 }
@@ -31,12 +39,7 @@ impl<'k> GpgClient<'k> {
         )
         .with_context(|| anyhow!("creating temporary file"))?;
 
-        let reader = io::BufReader::new(from);
-        if verify {
-            gpgrv::verify_message(reader, &to, &self.keyring)?;
-        } else {
-            gpgrv::read_doc(reader, &to)?;
-        }
+        self.read_clearsigned_from(io::BufReader::new(from), &to, verify)?;
 
         to.persist_by_rename(dest)
             .map_err(|e| e.error)
@@ -45,6 +48,21 @@ impl<'k> GpgClient<'k> {
         Ok(())
     }
 
+    /// Strip the signature from a clearsigned document, checking it first if `verify`.
+    pub fn read_clearsigned_from<R: BufRead, W: Write>(
+        &self,
+        from: R,
+        to: W,
+        verify: bool,
+    ) -> Result<(), Error> {
+        if verify {
+            gpgrv::verify_message(from, to, self.keyring)?;
+        } else {
+            gpgrv::read_doc(from, to)?;
+        }
+        Ok(())
+    }
+
     pub fn verify_detached<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
         &mut self,
         file: P,
@@ -62,3 +80,57 @@ impl<'k> GpgClient<'k> {
         Ok(())
     }
 }
+
+/// Build a `Keyring` containing only the keys named by a `signed-by` option.
+pub fn load_keyring(signed_by: &SignedBy) -> Result<Keyring, Error> {
+    let mut keyring = Keyring::new();
+    match signed_by {
+        SignedBy::Files(paths) => {
+            for path in paths {
+                let file = io::BufReader::new(
+                    fs::File::open(path)
+                        .with_context(|| anyhow!("opening key file: {:?}", path))?,
+                );
+                if path.extension() == Some(OsStr::new("asc")) {
+                    keyring.append_keys_from_armoured(file)
+                } else {
+                    keyring.append_keys_from(file)
+                }
+                .with_context(|| anyhow!("loading keys from {:?}", path))?;
+            }
+        }
+        SignedBy::Inline(block) => {
+            keyring
+                .append_keys_from_armoured(io::Cursor::new(block.as_bytes()))
+                .with_context(|| anyhow!("loading inline keys"))?;
+        }
+    }
+    Ok(keyring)
+}
+
+/// Read a document which may be clearsigned, e.g. a `.dsc`, returning its content,
+/// and whether it was signed. If there's a `keyring`, a good signature from it is required.
+pub(crate) fn read_maybe_clearsigned<R: BufRead>(
+    mut from: R,
+    keyring: Option<&Keyring>,
+) -> Result<(String, bool), Error> {
+    let signed = from
+        .fill_buf()?
+        .starts_with(BEGIN_SIGNED_MESSAGE.as_bytes());
+
+    let mut body = Vec::new();
+    if signed {
+        let empty = Keyring::new();
+        GpgClient::new(keyring.unwrap_or(&empty))
+            .read_clearsigned_from(from, &mut body, keyring.is_some())
+            .with_context(|| anyhow!("reading signature"))?;
+    } else {
+        ensure!(
+            keyring.is_none(),
+            "signature required, but document isn't signed"
+        );
+        io::copy(&mut from, &mut body)?;
+    }
+
+    Ok((String::from_utf8(body)?, signed))
+}
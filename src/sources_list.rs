@@ -1,11 +1,18 @@
-//! Load `Entry` objects from from a _classic_ sources list. (e.g. `/etc/*apt/sources.list`).
+//! Load `Entry` objects from from a _classic_ sources list. (e.g. `/etc/*apt/sources.list`),
+//! or from a `deb822`-style sources file (e.g. `/etc/apt/sources.list.d/*.sources`).
 
 use std::io::BufRead;
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Error;
+use insideout::InsideOut;
+
+use crate::rfc822;
+use crate::rfc822::RfcMapExt;
 
 /// Our representation of a classic sources list entry.
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -16,12 +23,27 @@ pub struct Entry {
     pub components: Vec<String>,
     pub arch: Option<Vec<String>>,
     pub untrusted: bool,
+    pub signed_by: Option<SignedBy>,
+    /// Reject the _Release_ if its `Valid-Until` has passed. On by default.
+    pub check_valid_until: bool,
+}
+
+/// The keys which are allowed to sign a source's _Release_, from the `signed-by` option.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub enum SignedBy {
+    /// Keyring files, e.g. `/usr/share/keyrings/debian-archive-keyring.gpg`.
+    /// Files ending in `.asc` are expected to be armoured.
+    Files(Vec<PathBuf>),
+    /// An armoured public key block, embedded directly in a `.sources` file.
+    Inline(String),
 }
 
 #[derive(Default)]
 struct ParsedOpts {
     arch: Option<Vec<String>>,
     untrusted: Option<bool>,
+    signed_by: Option<SignedBy>,
+    check_valid_until: Option<bool>,
 }
 
 fn parse_opts(opt_parts: Vec<&str>) -> Result<ParsedOpts, Error> {
@@ -32,6 +54,8 @@ fn parse_opts(opt_parts: Vec<&str>) -> Result<ParsedOpts, Error> {
             2 => match parts[0] {
                 "arch" => ret.arch = Some(parts[1].split(',').map(|s| s.to_owned()).collect()),
                 "untrusted" => ret.untrusted = Some(parts[1] == "yes"),
+                "check-valid-until" => ret.check_valid_until = Some(parts[1] != "no"),
+                "signed-by" => ret.signed_by = Some(signed_by_files(parts[1].split(','))?),
                 other => bail!("unknown option: {}", other),
             },
             _ => bail!("multiple = in option"),
@@ -100,15 +124,13 @@ fn read_single_line(line: &str) -> Result<Vec<Entry>, Error> {
     for src in srcs {
         ret.push(Entry {
             src: *src,
-            url: if url.ends_with('/') {
-                url.to_string()
-            } else {
-                format!("{}/", url)
-            },
+            url: with_trailing_slash(url),
             suite_codename: suite.to_string(),
             components: components.iter().map(|x| x.to_string()).collect(),
             arch: parsed_opts.arch.clone(),
             untrusted: parsed_opts.untrusted.unwrap_or(false),
+            signed_by: parsed_opts.signed_by.clone(),
+            check_valid_until: parsed_opts.check_valid_until.unwrap_or(true),
         });
     }
 
@@ -134,12 +156,155 @@ pub fn read<R: BufRead>(from: R) -> Result<Vec<Entry>, Error> {
         .collect())
 }
 
+fn with_trailing_slash(url: &str) -> String {
+    if url.ends_with('/') {
+        url.to_string()
+    } else {
+        format!("{}/", url)
+    }
+}
+
+/// `apt` also accepts key fingerprints here, to pick keys from its trusted keyrings,
+/// but we can only identify keys by their 64-bit id, so those are rejected.
+fn signed_by_files<'s, I: IntoIterator<Item = &'s str>>(paths: I) -> Result<SignedBy, Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        let fingerprint = path.trim_end_matches('!');
+        if 40 == fingerprint.len() && fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!(
+                "signed-by fingerprints (like {:?}) aren't supported; use a keyring file",
+                path
+            );
+        }
+        files.push(PathBuf::from(path));
+    }
+    Ok(SignedBy::Files(files))
+}
+
+fn read_signed_by(lines: &[&str]) -> Result<SignedBy, Error> {
+    if lines
+        .first()
+        .map(|line| line.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----"))
+        .unwrap_or(false)
+    {
+        // deb822 can't represent empty lines, so they are written as a single `.`
+        let block: Vec<&str> = lines
+            .iter()
+            .map(|&line| if "." == line { "" } else { line })
+            .collect();
+        return Ok(SignedBy::Inline(block.join("\n")));
+    }
+
+    signed_by_files(
+        lines
+            .iter()
+            .flat_map(|line| line.split(|c: char| ',' == c || c.is_whitespace()))
+            .filter(|path| !path.is_empty()),
+    )
+}
+
+fn read_stanza(stanza: &str) -> Result<Vec<Entry>, Error> {
+    let map = rfc822::fields_in_block(stanza).collect_to_map()?;
+
+    match map.get_value("Enabled").one_line()? {
+        Some("no") => return Ok(Vec::new()),
+        Some("yes") | None => (),
+        Some(other) => bail!("invalid value for Enabled: {:?}", other),
+    }
+
+    let mut srcs = Vec::with_capacity(2);
+    for src in map.get_value("Types").split_whitespace()? {
+        srcs.push(match src.as_str() {
+            "deb" => false,
+            "deb-src" => true,
+            other => bail!("unsupported Types entry: {:?}", other),
+        });
+    }
+
+    let urls = map.get_value("URIs").split_whitespace()?;
+    let suites = map.get_value("Suites").split_whitespace()?;
+    // Flat repositories, e.g. `Suites: ./`, don't have components.
+    let components = match map.get("Components") {
+        Some(_) => map.get_value("Components").split_whitespace()?,
+        None => Vec::new(),
+    };
+
+    let arch = match map.get("Architectures") {
+        Some(_) => Some(map.get_value("Architectures").split_whitespace()?),
+        None => None,
+    };
+
+    // `Trusted: yes` means "don't check the signature", which we call `untrusted`
+    let untrusted = map
+        .get_value("Trusted")
+        .one_line()?
+        .map(|v| "yes" == v)
+        .unwrap_or(false);
+
+    let check_valid_until = map
+        .get_value("Check-Valid-Until")
+        .one_line()?
+        .map(|v| "no" != v)
+        .unwrap_or(true);
+
+    let signed_by = map
+        .get("Signed-By")
+        .map(|lines| read_signed_by(lines))
+        .inside_out()?;
+
+    let mut ret = Vec::with_capacity(srcs.len() * urls.len() * suites.len());
+
+    for src in &srcs {
+        for url in &urls {
+            for suite in &suites {
+                ensure!(
+                    !components.is_empty() || suite.ends_with('/'),
+                    "Components is required, unless the suite is a path ending in '/': {:?}",
+                    suite
+                );
+                ret.push(Entry {
+                    src: *src,
+                    url: with_trailing_slash(url),
+                    suite_codename: suite.to_string(),
+                    components: components.clone(),
+                    arch: arch.clone(),
+                    untrusted,
+                    signed_by: signed_by.clone(),
+                    check_valid_until,
+                });
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Read `Entry` objects from a `deb822`-style `.sources` file.
+///
+/// Each stanza may expand to multiple entries, one for each combination
+/// of `Types`, `URIs` and `Suites`. Stanzas with `Enabled: no` are skipped.
+pub fn read_deb822<R: BufRead>(from: R) -> Result<Vec<Entry>, Error> {
+    let mut ret = Vec::new();
+    for (start, stanza) in rfc822::commented_blocks(from)? {
+        ret.extend(
+            read_stanza(&stanza)
+                .with_context(|| anyhow!("parsing stanza at line {}", start + 1))?,
+        );
+    }
+
+    Ok(ret)
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
 
+    use std::path::PathBuf;
+
     use super::read;
+    use super::read_deb822;
     use super::Entry;
+    use super::SignedBy;
 
     #[test]
     fn simple() {
@@ -152,6 +317,8 @@ mod tests {
                     suite_codename: "bar".to_string(),
                     components: vec!["baz".to_string(), "quux".to_string()],
                     untrusted: false,
+                    signed_by: None,
+                    check_valid_until: true,
                 },
                 Entry {
                     src: true,
@@ -160,6 +327,8 @@ mod tests {
                     suite_codename: "bar".to_string(),
                     components: vec!["baz".to_string(), "quux".to_string()],
                     untrusted: false,
+                    signed_by: None,
+                    check_valid_until: true,
                 },
             ],
             read(io::Cursor::new(
@@ -182,6 +351,8 @@ deb-src http://foo  bar  baz quux
                 suite_codename: "bar".to_string(),
                 components: vec!["baz".to_string(), "quux".to_string()],
                 untrusted: false,
+                signed_by: None,
+                check_valid_until: true,
             },],
             read(io::Cursor::new(
                 r"
@@ -202,6 +373,8 @@ deb [arch=amd64] http://foo  bar  baz quux
                 suite_codename: "bar".to_string(),
                 components: vec!["baz".to_string(), "quux".to_string()],
                 untrusted: true,
+                signed_by: None,
+                check_valid_until: true,
             },],
             read(io::Cursor::new(
                 r"
@@ -222,6 +395,8 @@ deb [untrusted=yes] http://foo  bar  baz quux
                 suite_codename: "bar".to_string(),
                 components: vec!["baz".to_string(), "quux".to_string()],
                 untrusted: false,
+                signed_by: None,
+                check_valid_until: true,
             },],
             read(io::Cursor::new(
                 r"
@@ -242,6 +417,8 @@ deb [untrusted=yeppers] http://foo  bar  baz quux
                 suite_codename: "bar".to_string(),
                 components: vec!["baz".to_string(), "quux".to_string()],
                 untrusted: true,
+                signed_by: None,
+                check_valid_until: true,
             },],
             read(io::Cursor::new(
                 r"
@@ -262,6 +439,8 @@ deb [untrusted=yes arch=amd64] http://foo  bar  baz quux
                 suite_codename: "bar".to_string(),
                 components: vec!["baz".to_string(), "quux".to_string()],
                 untrusted: false,
+                signed_by: None,
+                check_valid_until: true,
             },],
             read(io::Cursor::new(
                 r"
@@ -271,4 +450,249 @@ deb [arch=amd64,i386] http://foo  bar  baz quux
             .unwrap()
         );
     }
+
+    #[test]
+    fn deb822() {
+        assert_eq!(
+            vec![
+                Entry {
+                    src: false,
+                    arch: None,
+                    url: "http://foo/".to_string(),
+                    suite_codename: "bar".to_string(),
+                    components: vec!["baz".to_string(), "quux".to_string()],
+                    untrusted: false,
+                    signed_by: Some(SignedBy::Files(vec![PathBuf::from(
+                        "/usr/share/keyrings/foo.gpg",
+                    )])),
+                    check_valid_until: true,
+                },
+                Entry {
+                    src: false,
+                    arch: None,
+                    url: "http://foo/".to_string(),
+                    suite_codename: "bar-updates".to_string(),
+                    components: vec!["baz".to_string(), "quux".to_string()],
+                    untrusted: false,
+                    signed_by: Some(SignedBy::Files(vec![PathBuf::from(
+                        "/usr/share/keyrings/foo.gpg",
+                    )])),
+                    check_valid_until: true,
+                },
+                Entry {
+                    src: true,
+                    arch: None,
+                    url: "http://foo/".to_string(),
+                    suite_codename: "bar".to_string(),
+                    components: vec!["baz".to_string(), "quux".to_string()],
+                    untrusted: false,
+                    signed_by: Some(SignedBy::Files(vec![PathBuf::from(
+                        "/usr/share/keyrings/foo.gpg",
+                    )])),
+                    check_valid_until: true,
+                },
+                Entry {
+                    src: true,
+                    arch: None,
+                    url: "http://foo/".to_string(),
+                    suite_codename: "bar-updates".to_string(),
+                    components: vec!["baz".to_string(), "quux".to_string()],
+                    untrusted: false,
+                    signed_by: Some(SignedBy::Files(vec![PathBuf::from(
+                        "/usr/share/keyrings/foo.gpg",
+                    )])),
+                    check_valid_until: true,
+                },
+            ],
+            read_deb822(io::Cursor::new(
+                r"
+# a comment
+Types: deb deb-src
+URIs: http://foo
+Suites: bar bar-updates
+Components: baz quux
+Signed-By: /usr/share/keyrings/foo.gpg
+",
+            ))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn deb822_multiple_stanzas() {
+        assert_eq!(
+            vec![
+                Entry {
+                    src: false,
+                    arch: Some(vec!["amd64".to_string(), "i386".to_string()]),
+                    url: "http://foo/".to_string(),
+                    suite_codename: "bar".to_string(),
+                    components: vec!["baz".to_string()],
+                    untrusted: false,
+                    signed_by: None,
+                    check_valid_until: true,
+                },
+                Entry {
+                    src: false,
+                    arch: None,
+                    url: "http://quux/".to_string(),
+                    suite_codename: "bar".to_string(),
+                    components: vec!["baz".to_string()],
+                    untrusted: true,
+                    signed_by: None,
+                    check_valid_until: true,
+                },
+            ],
+            read_deb822(io::Cursor::new(
+                r"
+Types: deb
+URIs: http://foo
+Suites: bar
+Components: baz
+Architectures: amd64 i386
+
+
+Types: deb
+URIs: http://disabled
+Suites: bar
+Components: baz
+Enabled: no
+
+Types: deb
+URIs: http://quux/
+Suites: bar
+Components: baz
+Trusted: yes
+",
+            ))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn signed_by() {
+        assert_eq!(
+            vec![Entry {
+                src: false,
+                arch: None,
+                url: "http://foo/".to_string(),
+                suite_codename: "bar".to_string(),
+                components: vec!["baz".to_string()],
+                untrusted: false,
+                signed_by: Some(SignedBy::Files(vec![
+                    PathBuf::from("/usr/share/keyrings/foo.gpg"),
+                    PathBuf::from("/etc/foo.asc"),
+                ])),
+                check_valid_until: true,
+            },],
+            read(io::Cursor::new(
+                r"
+deb [signed-by=/usr/share/keyrings/foo.gpg,/etc/foo.asc] http://foo bar baz
+",
+            ))
+            .unwrap()
+        );
+
+        assert!(read(io::Cursor::new(
+            "deb [signed-by=A7236886F3CCCAAD148A27F80E98404D386FA1D9] http://foo bar baz\n",
+        ))
+        .is_err());
+        assert!(read_deb822(io::Cursor::new(
+            r"
+Types: deb
+URIs: http://foo
+Suites: bar
+Components: baz
+Signed-By: /usr/share/keyrings/foo.gpg A7236886F3CCCAAD148A27F80E98404D386FA1D9!
+",
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn deb822_flat() {
+        assert_eq!(
+            vec![Entry {
+                src: false,
+                arch: None,
+                url: "http://foo/repo/".to_string(),
+                suite_codename: "./".to_string(),
+                components: Vec::new(),
+                untrusted: false,
+                signed_by: None,
+                check_valid_until: true,
+            },],
+            read_deb822(io::Cursor::new(
+                r"
+Types: deb
+URIs: http://foo/repo
+Suites: ./
+",
+            ))
+            .unwrap()
+        );
+
+        assert!(read_deb822(io::Cursor::new(
+            r"
+Types: deb
+URIs: http://foo
+Suites: bar
+",
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn deb822_inline_key() {
+        let entries = read_deb822(io::Cursor::new(
+            r"
+Types: deb
+URIs: http://foo
+Suites: bar
+Components: baz
+Signed-By:
+ -----BEGIN PGP PUBLIC KEY BLOCK-----
+ .
+ mDMEYCQjIxYJKwYBBAHaRw8BAQdAD/P5Nvvnvk66SxBBHDbhRml9ORg1WV5CvzKY
+ =oxO2
+ -----END PGP PUBLIC KEY BLOCK-----
+",
+        ))
+        .unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(
+            Some(SignedBy::Inline(
+                concat!(
+                    "-----BEGIN PGP PUBLIC KEY BLOCK-----\n",
+                    "\n",
+                    "mDMEYCQjIxYJKwYBBAHaRw8BAQdAD/P5Nvvnvk66SxBBHDbhRml9ORg1WV5CvzKY\n",
+                    "=oxO2\n",
+                    "-----END PGP PUBLIC KEY BLOCK-----",
+                )
+                .to_string()
+            )),
+            entries[0].signed_by
+        );
+    }
+
+    #[test]
+    fn check_valid_until() {
+        let classic = read(io::Cursor::new(
+            "deb [check-valid-until=no] http://foo bar baz",
+        ))
+        .unwrap();
+        assert!(!classic[0].check_valid_until);
+
+        let deb822 = read_deb822(io::Cursor::new(
+            r"
+Types: deb
+URIs: http://foo
+Suites: bar
+Components: baz
+Check-Valid-Until: no
+",
+        ))
+        .unwrap();
+        assert!(!deb822[0].check_valid_until);
+    }
 }
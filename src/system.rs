@@ -20,6 +20,7 @@ use std::path::PathBuf;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
+use chrono::Duration;
 use gpgrv::Keyring;
 use reqwest;
 
@@ -36,7 +37,8 @@ pub struct System {
     sources_entries: Vec<Entry>,
     arches: Vec<String>,
     keyring: Keyring,
-    client: reqwest::Client,
+    max_valid_time: Option<Duration>,
+    pub(crate) client: reqwest::Client,
 }
 
 /// A _Listing_ that has been downloaded, and the _Release_ it came from.
@@ -75,6 +77,7 @@ impl System {
             sources_entries: Vec::new(),
             arches: Vec::new(),
             keyring: Keyring::new(),
+            max_valid_time: None,
             client,
         })
     }
@@ -94,6 +97,11 @@ impl System {
         self.arches = arches.into_iter().map(|x| x.to_string()).collect();
     }
 
+    /// The configured architectures, the primary one first.
+    pub fn arches(&self) -> &[String] {
+        &self.arches
+    }
+
     /// Configure the location of the `dpkg` database.
     ///
     /// This can be used to view `status` information, i.e. information on
@@ -110,14 +118,28 @@ impl System {
         Ok(())
     }
 
+    /// Refuse to use any _Release_ whose `Date` is older than this, even if its
+    /// `Valid-Until` (if any) says it is still valid. Like apt's `Acquire::Max-ValidTime`.
+    pub fn set_max_valid_time(&mut self, max_valid_time: Option<Duration>) {
+        self.max_valid_time = max_valid_time;
+    }
+
     /// Download any necessary _Listings_ for the configured _Sources Entries_.
+    ///
+    /// _Releases_ which have expired, or which are older than the ones already
+    /// downloaded, are rejected.
     pub async fn update(&self) -> Result<bool, Error> {
         let requested =
             release::RequestedReleases::from_sources_lists(&self.sources_entries, &self.arches)
                 .with_context(|| anyhow!("parsing sources entries"))?;
 
         requested
-            .download(&self.lists_dir, &self.keyring, &self.client)
+            .download(
+                &self.lists_dir,
+                &self.keyring,
+                &self.client,
+                self.max_valid_time,
+            )
             .await
             .with_context(|| anyhow!("downloading releases"))?;
 
@@ -0,0 +1,273 @@
+//! Compare what's installed (according to `dpkg`) with what's available in the _Listings_,
+//! like `apt list --upgradable`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use insideout::InsideOut;
+
+use crate::parse::State;
+use crate::parse::Status;
+use crate::parse::Version;
+use crate::policy::Origin;
+use crate::rfc822::RfcMapExt;
+use crate::system::NamedBlock;
+use crate::system::System;
+
+/// An installed package, and the newest version of it which is available.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Installed {
+    pub name: String,
+    /// The `Architecture`, which may be `all`.
+    pub arch: String,
+    pub version: Version,
+    pub status: Status,
+    /// The newest version available for the same architecture (or `all`), and where from.
+    /// For `all` packages, that's the primary architecture, or `all`.
+    pub newest: Option<(Version, Origin)>,
+}
+
+/// How an installed package relates to what's available.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    UpToDate,
+    /// A newer version is available.
+    Upgradable,
+    /// A newer version is available, but the package is held.
+    Held,
+    /// Only older versions are available.
+    Downgrade,
+    /// No version is available at all, e.g. it was removed from the archive,
+    /// or was installed from a local `.deb`.
+    Obsolete,
+}
+
+impl Installed {
+    /// The multi-arch identity, e.g. `libc6:amd64`, or `tzdata:all`.
+    pub fn identity(&self) -> String {
+        format!("{}:{}", self.name, self.arch)
+    }
+
+    pub fn kind(&self) -> Kind {
+        match &self.newest {
+            None => Kind::Obsolete,
+            Some((newest, _)) if *newest > self.version && self.status.is_held() => Kind::Held,
+            Some((newest, _)) if *newest > self.version => Kind::Upgradable,
+            Some((newest, _)) if *newest < self.version => Kind::Downgrade,
+            Some(_) => Kind::UpToDate,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Kind::UpToDate => "up-to-date",
+            Kind::Upgradable => "upgradable",
+            Kind::Held => "held",
+            Kind::Downgrade => "downgrade",
+            Kind::Obsolete => "obsolete",
+        })
+    }
+}
+
+/// Every package the `dpkg` database says is (at least partly) installed,
+/// with the newest version in the downloaded binary _Listings_, sorted by identity.
+///
+/// Invalid stanzas, in the _Listings_ or the database, are reported (to stderr) and skipped.
+pub fn report(system: &System) -> Result<Vec<Installed>, Error> {
+    let native = system
+        .arches()
+        .first()
+        .ok_or_else(|| anyhow!("no architectures configured"))?;
+
+    let mut available = Vec::new();
+    for list in system.listings()? {
+        if list.listing.arch.is_none() {
+            continue;
+        }
+        let origin = Origin::from_list(&list);
+        for block in system.open_listing(&list)? {
+            let (name, arch, version) = match identify(&block?) {
+                Ok(found) => found,
+                Err(e) => {
+                    writeln!(
+                        io::stderr(),
+                        "Skipping an invalid package in {} {}: {:?}",
+                        list.release.req.mirror,
+                        list.listing.component,
+                        e
+                    )?;
+                    continue;
+                }
+            };
+            available.push((name, arch, version, origin.clone()));
+        }
+    }
+
+    let mut installed = Vec::new();
+    for block in system.open_status()? {
+        match installed_package(&block?) {
+            Ok(Some(package)) => installed.push(package),
+            Ok(None) => (),
+            Err(e) => writeln!(
+                io::stderr(),
+                "Skipping an invalid package in the dpkg database: {:?}",
+                e
+            )?,
+        }
+    }
+
+    Ok(compare(native, installed, available))
+}
+
+/// The `Package`, `Architecture` and `Version` of a _Block_.
+fn identify(block: &NamedBlock) -> Result<(String, String, Version), Error> {
+    let map = block.as_map()?;
+    Ok((
+        map.get_value("Package").one_line_req()?.to_string(),
+        map.get_value("Architecture").one_line_req()?.to_string(),
+        map.get_value("Version").one_line_req()?.parse()?,
+    ))
+}
+
+/// A _Block_ from the `dpkg` database, if it's (at least partly) installed.
+fn installed_package(
+    block: &NamedBlock,
+) -> Result<Option<(String, String, Version, Status)>, Error> {
+    let status: Option<Status> = block
+        .as_map()?
+        .get_value("Status")
+        .one_line()?
+        .map(|status| status.parse())
+        .inside_out()?;
+    let status = match status {
+        Some(status) if status.state > State::ConfigFiles => status,
+        _ => return Ok(None),
+    };
+    let (name, arch, version) = identify(block)?;
+    Ok(Some((name, arch, version, status)))
+}
+
+type Identity = (String, String);
+
+fn compare(
+    native: &str,
+    installed: Vec<(String, String, Version, Status)>,
+    available: Vec<(String, String, Version, Origin)>,
+) -> Vec<Installed> {
+    let mut newest: HashMap<Identity, (Version, Origin)> = HashMap::new();
+    for (name, arch, version, origin) in available {
+        let best = newest
+            .entry((name, arch))
+            .or_insert_with(|| (version.clone(), origin.clone()));
+        if version > best.0 {
+            *best = (version, origin);
+        }
+    }
+
+    let mut ret: Vec<Installed> = installed
+        .into_iter()
+        .map(|(name, arch, version, status)| {
+            // packages can move between `all` and an architecture between versions
+            let same = newest.get(&(name.clone(), arch.clone()));
+            let other = if "all" == arch {
+                newest.get(&(name.clone(), native.to_string()))
+            } else {
+                newest.get(&(name.clone(), "all".to_string()))
+            };
+            let newest = match (same, other) {
+                (Some(same), Some(other)) if other.0 > same.0 => Some(other),
+                (Some(same), _) => Some(same),
+                (None, other) => other,
+            }
+            .cloned();
+
+            Installed {
+                name,
+                arch,
+                version,
+                status,
+                newest,
+            }
+        })
+        .collect();
+
+    ret.sort_by_key(|installed| installed.identity());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare;
+    use super::Kind;
+    use crate::policy::Origin;
+
+    #[test]
+    fn kinds() {
+        let origin = |archive: &str| Origin {
+            archive: Some(archive.to_string()),
+            ..Origin::default()
+        };
+        let s = |s: &str| s.to_string();
+        let v = |v: &str| v.parse().unwrap();
+        let ok = "install ok installed".parse().unwrap();
+
+        let report = compare(
+            "amd64",
+            vec![
+                (s("libc6"), s("amd64"), v("2.36"), ok),
+                (s("libc6"), s("i386"), v("2.36"), ok),
+                (s("tzdata"), s("all"), v("2024a"), ok),
+                (
+                    s("vim"),
+                    s("amd64"),
+                    v("9.0"),
+                    "hold ok installed".parse().unwrap(),
+                ),
+                (s("local"), s("amd64"), v("1.0"), ok),
+                (s("git"), s("amd64"), v("3.0"), ok),
+                (s("moved"), s("all"), v("1.0"), ok),
+            ],
+            vec![
+                (s("libc6"), s("amd64"), v("2.37"), origin("unstable")),
+                (s("libc6"), s("amd64"), v("2.36"), origin("stable")),
+                (s("libc6"), s("i386"), v("2.36"), origin("stable")),
+                (s("tzdata"), s("all"), v("2024a"), origin("stable")),
+                (s("vim"), s("amd64"), v("9.1"), origin("stable")),
+                (s("git"), s("amd64"), v("2.39"), origin("stable")),
+                (s("tzdata"), s("arm64"), v("2025a"), origin("ports")),
+                (s("moved"), s("amd64"), v("2.0"), origin("unstable")),
+                (s("moved"), s("all"), v("1.0"), origin("stable")),
+            ],
+        );
+
+        let found: Vec<(String, Kind, Option<String>)> = report
+            .iter()
+            .map(|i| {
+                (
+                    i.identity(),
+                    i.kind(),
+                    i.newest.as_ref().and_then(|(_, o)| o.archive.clone()),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                (s("git:amd64"), Kind::Downgrade, Some(s("stable"))),
+                (s("libc6:amd64"), Kind::Upgradable, Some(s("unstable"))),
+                (s("libc6:i386"), Kind::UpToDate, Some(s("stable"))),
+                (s("local:amd64"), Kind::Obsolete, None),
+                (s("moved:all"), Kind::Upgradable, Some(s("unstable"))),
+                (s("tzdata:all"), Kind::UpToDate, Some(s("stable"))),
+                (s("vim:amd64"), Kind::Held, Some(s("stable"))),
+            ],
+            found
+        );
+    }
+}
@@ -13,7 +13,7 @@ fn parse(pkg: &str) -> Result<Package, Error> {
 fn parse_multiple_binaries() -> Result<(), Error> {
     let pkg = parse(include_str!("packages/alien-arena.pkg"))?;
 
-    assert_eq!("7.66+dfsg-5", pkg.version);
+    assert_eq!("7.66+dfsg-5", pkg.version.as_str());
 
     let src = pkg.as_src().unwrap();
 